@@ -1,21 +1,244 @@
+use crate::node::{Annotation, CloneState, Cons, Excludes, Nil, Node};
+
+/// Byte range into the source text, `start` inclusive and `end` exclusive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    #[must_use]
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+}
+
+/// A parsed program. `S` is the annotation state of its expression [`Node`]s, which the passes
+/// change by rebuilding the tree with [`Program::annotate`]
 #[derive(Debug)]
-pub struct Program {
-    pub nodes: Vec<Definition>,
+pub struct Program<S = Nil> {
+    /// Inner `//!` doc comments at the start of the file
+    pub docs: Vec<String>,
+    pub nodes: Vec<Item<S>>,
 }
 
+/// A definition together with the source range it was parsed from, including its doc comments
 #[derive(Debug)]
+pub struct Item<S = Nil> {
+    pub definition: Definition<S>,
+    pub span: Span,
+    /// Outer `///` doc comments, followed by inner `//!` doc comments of a mod. One per line
+    pub docs: Vec<String>,
+    pub attributes: Vec<Attribute>,
+    pub visibility: Visibility,
+}
+
+/// Where a definition can be named from. A private definition is visible in its mod and the mods
+/// nested in it, a `pub` one wherever its mod is visible
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Visibility {
+    #[default]
+    Private,
+    Public,
+}
+
+impl<S> Program<S> {
+    /// The body of every function of the program, including those in mods and impls and the
+    /// default methods of traits, in source order
+    #[must_use]
+    pub fn bodies(&self) -> Vec<&Node<Expr<S>, S>> {
+        fn collect<'a, S>(items: &'a [Item<S>], bodies: &mut Vec<&'a Node<Expr<S>, S>>) {
+            for item in items {
+                match &item.definition {
+                    Definition::Struct(..) | Definition::Use(_) => {}
+                    Definition::Trait(.., methods) => {
+                        bodies.extend(methods.iter().filter_map(|m| m.body.as_ref()));
+                    }
+                    Definition::Impl(Impl::Trait(.., fs) | Impl::Struct(_, fs)) => {
+                        bodies.extend(fs.iter().map(|f| &f.body));
+                    }
+                    Definition::Mod(_, children) => collect(children, bodies),
+                    Definition::Func(function) => bodies.push(&function.body),
+                }
+            }
+        }
+
+        let mut bodies = Vec::new();
+        collect(&self.nodes, &mut bodies);
+        bodies
+    }
+
+    /// The body of every function of the program, including those in mods and impls and the
+    /// default methods of traits, in source order
+    pub fn bodies_mut(&mut self) -> Vec<&mut Node<Expr<S>, S>> {
+        fn collect<'a, S>(items: &'a mut [Item<S>], bodies: &mut Vec<&'a mut Node<Expr<S>, S>>) {
+            for item in items {
+                match &mut item.definition {
+                    Definition::Struct(..) | Definition::Use(_) => {}
+                    Definition::Trait(.., methods) => {
+                        bodies.extend(methods.iter_mut().filter_map(|m| m.body.as_mut()));
+                    }
+                    Definition::Impl(Impl::Trait(.., fs) | Impl::Struct(_, fs)) => {
+                        bodies.extend(fs.iter_mut().map(|f| &mut f.body));
+                    }
+                    Definition::Mod(_, children) => collect(children, bodies),
+                    Definition::Func(function) => bodies.push(&mut function.body),
+                }
+            }
+        }
+
+        let mut bodies = Vec::new();
+        collect(&mut self.nodes, &mut bodies);
+        bodies
+    }
+
+    /// Rebuilds the program with the annotation `f` computes for each expression added to it,
+    /// moving every node to the state `Cons<U, S>`. An expression is passed to `f` before its
+    /// sub-expressions
+    #[must_use]
+    pub fn annotate<U, F>(self, f: &mut F) -> Program<Cons<U, S>>
+    where
+        U: Annotation,
+        S: Excludes<U>,
+        F: FnMut(&Node<Expr<S>, S>) -> U,
+    {
+        Program {
+            docs: self.docs,
+            nodes: self
+                .nodes
+                .into_iter()
+                .map(|item| item.annotate(f))
+                .collect(),
+        }
+    }
+}
+
+impl<S> Item<S> {
+    fn annotate<U, F>(self, f: &mut F) -> Item<Cons<U, S>>
+    where
+        U: Annotation,
+        S: Excludes<U>,
+        F: FnMut(&Node<Expr<S>, S>) -> U,
+    {
+        let definition = match self.definition {
+            Definition::Struct(ident, generics, fields) => {
+                Definition::Struct(ident, generics, fields)
+            }
+            Definition::Trait(ident, supertraits, methods) => Definition::Trait(
+                ident,
+                supertraits,
+                methods.into_iter().map(|m| m.annotate(f)).collect(),
+            ),
+            Definition::Impl(Impl::Trait(name, generics, trait_ident, ty, functions)) => {
+                let functions = functions.into_iter().map(|g| g.annotate(f)).collect();
+                Definition::Impl(Impl::Trait(name, generics, trait_ident, ty, functions))
+            }
+            Definition::Impl(Impl::Struct(ident, functions)) => {
+                let functions = functions.into_iter().map(|g| g.annotate(f)).collect();
+                Definition::Impl(Impl::Struct(ident, functions))
+            }
+            Definition::Mod(ident, items) => Definition::Mod(
+                ident,
+                items.into_iter().map(|item| item.annotate(f)).collect(),
+            ),
+            Definition::Func(function) => Definition::Func(Box::new(function.annotate(f))),
+            Definition::Use(ident) => Definition::Use(ident),
+        };
+        Item {
+            definition,
+            span: self.span,
+            docs: self.docs,
+            attributes: self.attributes,
+            visibility: self.visibility,
+        }
+    }
+}
+
+impl<S> Item<S> {
+    #[must_use]
+    pub fn attribute(&self, name: &str) -> Option<&Attribute> {
+        self.attributes.iter().find(|attr| attr.name == name)
+    }
+
+    #[must_use]
+    pub fn has_attribute(&self, name: &str) -> bool {
+        self.attribute(name).is_some()
+    }
+}
+
+/// `#[name]` or `#[name(args)]` in front of a definition
+#[derive(Debug)]
+pub struct Attribute {
+    pub name: String,
+    pub args: Vec<AttributeArg>,
+    pub span: Span,
+}
+
+#[derive(Debug)]
+pub enum AttributeArg {
+    Ident(String),
+    Str(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Ident {
-    Simple(String),
-    Qualified(Vec<String>, String),
+    Simple(String, Span),
+    Qualified(Vec<String>, String, Span),
+}
+
+impl Ident {
+    #[must_use]
+    pub fn span(&self) -> Span {
+        match self {
+            Ident::Simple(_, span) | Ident::Qualified(_, _, span) => *span,
+        }
+    }
+
+    pub fn span_mut(&mut self) -> &mut Span {
+        match self {
+            Ident::Simple(_, span) | Ident::Qualified(_, _, span) => span,
+        }
+    }
+
+    /// The last segment of the identifier
+    #[must_use]
+    pub fn name(&self) -> &str {
+        match self {
+            Ident::Simple(name, _) | Ident::Qualified(_, name, _) => name,
+        }
+    }
+
+    /// All segments of the identifier, including the last
+    #[must_use]
+    pub fn segments(&self) -> Vec<&str> {
+        match self {
+            Ident::Simple(name, _) => vec![name],
+            Ident::Qualified(path, name, _) => path
+                .iter()
+                .map(String::as_str)
+                .chain([name.as_str()])
+                .collect(),
+        }
+    }
+}
+
+impl std::fmt::Display for Ident {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.segments().join("::"))
+    }
 }
 
 #[derive(Debug)]
-pub enum Definition {
-    Struct(Ident, Vec<StructField>),
-    Trait(Ident, Vec<TraitMethod>),
-    Impl(Impl),
-    Mod(Ident, Vec<Definition>),
-    Func(Function),
+pub enum Definition<S = Nil> {
+    Struct(Ident, Vec<Generic>, Vec<StructField>),
+    /// `trait T: U + V { ... }`, with its supertraits and methods
+    Trait(Ident, Vec<Ident>, Vec<TraitMethod<S>>),
+    Impl(Impl<S>),
+    Mod(Ident, Vec<Item<S>>),
+    Func(Box<Function<S>>),
+    /// `use C;`, selecting an impl for every function of the enclosing mod
+    Use(Ident),
 }
 
 #[derive(Debug)]
@@ -25,42 +248,257 @@ pub struct StructField {
 }
 
 #[derive(Debug)]
-pub struct TraitMethod {
+pub struct TraitMethod<S = Nil> {
     pub ident: Ident,
+    /// The `self` parameter, if the method takes the value it is called on
+    pub receiver: Option<Ident>,
     pub parameters: Vec<Parameter>,
     pub return_type: Type,
+    /// The default body, used by impls that leave the method out
+    pub body: Option<Node<Expr<S>, S>>,
+    pub docs: Vec<String>,
 }
 
-#[derive(Debug)]
+impl<S> TraitMethod<S> {
+    fn annotate<U, F>(self, f: &mut F) -> TraitMethod<Cons<U, S>>
+    where
+        U: Annotation,
+        S: Excludes<U>,
+        F: FnMut(&Node<Expr<S>, S>) -> U,
+    {
+        TraitMethod {
+            ident: self.ident,
+            receiver: self.receiver,
+            parameters: self.parameters,
+            return_type: self.return_type,
+            body: self.body.map(|body| annotate_node(body, f)),
+            docs: self.docs,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Parameter {
     pub ident: Ident,
     pub parameter_type: Type,
 }
 
+/// An expression. Every sub-expression is wrapped in a [`Node`], which the parser annotates with
+/// its [`Span`]. `S` is the annotation state of those nodes
 #[derive(Debug)]
-pub enum Expr {
+pub enum Expr<S = Nil> {
     Literal(Literal),
     Ident(Ident),
-    Paren(Box<Expr>),
-    Binary(Box<Expr>, BinOp, Box<Expr>),
-    Let(Ident, Option<Type>, Box<Expr>),
-    If(Box<Expr>, Box<Expr>, Option<Box<Expr>>),
-    Block(Vec<Expr>, Option<Box<Expr>>),
-    Invocation(Ident, Vec<Expr>),
-    Use(Ident, Box<Expr>),
-    StructInit(Ident, Vec<(Ident, Expr)>),
+    Paren(Box<Node<Expr<S>, S>>),
+    Unary(UnOp, Box<Node<Expr<S>, S>>),
+    Binary(Box<Node<Expr<S>, S>>, BinOp, Box<Node<Expr<S>, S>>),
+    Let(Ident, Option<Type>, Box<Node<Expr<S>, S>>),
+    If(
+        Box<Node<Expr<S>, S>>,
+        Box<Node<Expr<S>, S>>,
+        Option<Box<Node<Expr<S>, S>>>,
+    ),
+    Block(Vec<Node<Expr<S>, S>>, Option<Box<Node<Expr<S>, S>>>),
+    Invocation(Ident, Vec<Node<Expr<S>, S>>),
+    /// A method called on a receiver other than a local or a field of one, like `(a).m()`
+    MethodCall(Box<Node<Expr<S>, S>>, Ident, Vec<Node<Expr<S>, S>>),
+    Use(Ident, Box<Node<Expr<S>, S>>),
+    StructInit(Ident, Vec<(Ident, Node<Expr<S>, S>)>),
 }
 
-#[derive(Debug)]
+// Written out rather than derived, which would bound `S` by `Clone` and `PartialEq` instead of
+// what nodes need
+impl<S: CloneState> Clone for Expr<S> {
+    fn clone(&self) -> Self {
+        match self {
+            Expr::Literal(literal) => Expr::Literal(literal.clone()),
+            Expr::Ident(ident) => Expr::Ident(ident.clone()),
+            Expr::Paren(expr) => Expr::Paren(expr.clone()),
+            Expr::Unary(op, expr) => Expr::Unary(op.clone(), expr.clone()),
+            Expr::Binary(lhs, op, rhs) => Expr::Binary(lhs.clone(), op.clone(), rhs.clone()),
+            Expr::Let(ident, ty, expr) => Expr::Let(ident.clone(), ty.clone(), expr.clone()),
+            Expr::If(cond, then, otherwise) => {
+                Expr::If(cond.clone(), then.clone(), otherwise.clone())
+            }
+            Expr::Block(statements, tail) => Expr::Block(statements.clone(), tail.clone()),
+            Expr::Invocation(ident, args) => Expr::Invocation(ident.clone(), args.clone()),
+            Expr::MethodCall(receiver, method, args) => {
+                Expr::MethodCall(receiver.clone(), method.clone(), args.clone())
+            }
+            Expr::Use(path, expr) => Expr::Use(path.clone(), expr.clone()),
+            Expr::StructInit(ident, fields) => Expr::StructInit(ident.clone(), fields.clone()),
+        }
+    }
+}
+
+impl<S: CloneState> PartialEq for Expr<S> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Expr::Literal(a), Expr::Literal(b)) => a == b,
+            (Expr::Ident(a), Expr::Ident(b)) => a == b,
+            (Expr::Paren(a), Expr::Paren(b)) => a == b,
+            (Expr::Unary(op_a, a), Expr::Unary(op_b, b)) => op_a == op_b && a == b,
+            (Expr::Binary(lhs_a, op_a, rhs_a), Expr::Binary(lhs_b, op_b, rhs_b)) => {
+                lhs_a == lhs_b && op_a == op_b && rhs_a == rhs_b
+            }
+            (Expr::Let(ident_a, ty_a, a), Expr::Let(ident_b, ty_b, b)) => {
+                ident_a == ident_b && ty_a == ty_b && a == b
+            }
+            (Expr::If(cond_a, then_a, else_a), Expr::If(cond_b, then_b, else_b)) => {
+                cond_a == cond_b && then_a == then_b && else_a == else_b
+            }
+            (Expr::Block(statements_a, tail_a), Expr::Block(statements_b, tail_b)) => {
+                statements_a == statements_b && tail_a == tail_b
+            }
+            (Expr::Invocation(ident_a, args_a), Expr::Invocation(ident_b, args_b)) => {
+                ident_a == ident_b && args_a == args_b
+            }
+            (
+                Expr::MethodCall(receiver_a, method_a, args_a),
+                Expr::MethodCall(receiver_b, method_b, args_b),
+            ) => receiver_a == receiver_b && method_a == method_b && args_a == args_b,
+            (Expr::Use(path_a, a), Expr::Use(path_b, b)) => path_a == path_b && a == b,
+            (Expr::StructInit(ident_a, fields_a), Expr::StructInit(ident_b, fields_b)) => {
+                ident_a == ident_b && fields_a == fields_b
+            }
+            _ => false,
+        }
+    }
+}
+
+/// An expression node moved to the state `Cons<U, S>`
+type Annotated<U, S> = Node<Expr<Cons<U, S>>, Cons<U, S>>;
+
+/// Adds the annotation `f` computes to `node` and every sub-expression of it
+fn annotate_node<S, U, F>(node: Node<Expr<S>, S>, f: &mut F) -> Annotated<U, S>
+where
+    U: Annotation,
+    S: Excludes<U>,
+    F: FnMut(&Node<Expr<S>, S>) -> U,
+{
+    let annotation = f(&node);
+    node.add_annotation(annotation)
+        .map_node(|expr| expr.annotate(f))
+}
+
+fn annotate_boxed<S, U, F>(node: Box<Node<Expr<S>, S>>, f: &mut F) -> Box<Annotated<U, S>>
+where
+    U: Annotation,
+    S: Excludes<U>,
+    F: FnMut(&Node<Expr<S>, S>) -> U,
+{
+    Box::new(annotate_node(*node, f))
+}
+
+impl<S> Expr<S> {
+    /// The direct sub-expressions, in source order
+    #[must_use]
+    pub fn children(&self) -> Vec<&Node<Expr<S>, S>> {
+        match self {
+            Expr::Literal(_) | Expr::Ident(_) => vec![],
+            Expr::Paren(e) | Expr::Unary(_, e) | Expr::Let(_, _, e) | Expr::Use(_, e) => vec![e],
+            Expr::Binary(left, _, right) => vec![left, right],
+            Expr::If(cond, yes, no) => [cond, yes]
+                .into_iter()
+                .chain(no)
+                .map(AsRef::as_ref)
+                .collect(),
+            Expr::Block(statements, tail) => statements
+                .iter()
+                .chain(tail.iter().map(AsRef::as_ref))
+                .collect(),
+            Expr::Invocation(_, args) => args.iter().collect(),
+            Expr::MethodCall(receiver, _, args) => {
+                std::iter::once(receiver.as_ref()).chain(args).collect()
+            }
+            Expr::StructInit(_, fields) => fields.iter().map(|(_, value)| value).collect(),
+        }
+    }
+
+    /// The direct sub-expressions, in source order
+    pub fn children_mut(&mut self) -> Vec<&mut Node<Expr<S>, S>> {
+        match self {
+            Expr::Literal(_) | Expr::Ident(_) => vec![],
+            Expr::Paren(e) | Expr::Unary(_, e) | Expr::Let(_, _, e) | Expr::Use(_, e) => {
+                vec![e.as_mut()]
+            }
+            Expr::Binary(left, _, right) => vec![left.as_mut(), right.as_mut()],
+            Expr::If(cond, yes, no) => [cond, yes]
+                .into_iter()
+                .chain(no)
+                .map(AsMut::as_mut)
+                .collect(),
+            Expr::Block(statements, tail) => statements
+                .iter_mut()
+                .chain(tail.iter_mut().map(AsMut::as_mut))
+                .collect(),
+            Expr::Invocation(_, args) => args.iter_mut().collect(),
+            Expr::MethodCall(receiver, _, args) => {
+                std::iter::once(receiver.as_mut()).chain(args).collect()
+            }
+            Expr::StructInit(_, fields) => fields.iter_mut().map(|(_, value)| value).collect(),
+        }
+    }
+
+    fn annotate<U, F>(self, f: &mut F) -> Expr<Cons<U, S>>
+    where
+        U: Annotation,
+        S: Excludes<U>,
+        F: FnMut(&Node<Expr<S>, S>) -> U,
+    {
+        match self {
+            Expr::Literal(literal) => Expr::Literal(literal),
+            Expr::Ident(ident) => Expr::Ident(ident),
+            Expr::Paren(e) => Expr::Paren(annotate_boxed(e, f)),
+            Expr::Unary(op, e) => Expr::Unary(op, annotate_boxed(e, f)),
+            Expr::Binary(left, op, right) => {
+                Expr::Binary(annotate_boxed(left, f), op, annotate_boxed(right, f))
+            }
+            Expr::Let(ident, t, value) => Expr::Let(ident, t, annotate_boxed(value, f)),
+            Expr::If(cond, yes, no) => Expr::If(
+                annotate_boxed(cond, f),
+                annotate_boxed(yes, f),
+                no.map(|no| annotate_boxed(no, f)),
+            ),
+            Expr::Block(statements, tail) => Expr::Block(
+                statements
+                    .into_iter()
+                    .map(|statement| annotate_node(statement, f))
+                    .collect(),
+                tail.map(|tail| annotate_boxed(tail, f)),
+            ),
+            Expr::Invocation(ident, args) => Expr::Invocation(
+                ident,
+                args.into_iter().map(|arg| annotate_node(arg, f)).collect(),
+            ),
+            Expr::MethodCall(receiver, ident, args) => Expr::MethodCall(
+                annotate_boxed(receiver, f),
+                ident,
+                args.into_iter().map(|arg| annotate_node(arg, f)).collect(),
+            ),
+            Expr::Use(ident, e) => Expr::Use(ident, annotate_boxed(e, f)),
+            Expr::StructInit(ident, fields) => Expr::StructInit(
+                ident,
+                fields
+                    .into_iter()
+                    .map(|(field, value)| (field, annotate_node(value, f)))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Literal {
     I32(i32),
     F32(f32),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum BinOp {
     Mult,
     Div,
+    Modulo,
     Plus,
     Minus,
     LT,
@@ -68,28 +506,157 @@ pub enum BinOp {
     GT,
     GTE,
     Equal,
+    NotEqual,
+    And,
+    Or,
 }
 
-#[derive(Debug)]
+impl BinOp {
+    #[must_use]
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            BinOp::Mult => "*",
+            BinOp::Div => "/",
+            BinOp::Modulo => "%",
+            BinOp::Plus => "+",
+            BinOp::Minus => "-",
+            BinOp::LT => "<",
+            BinOp::LTE => "<=",
+            BinOp::GT => ">",
+            BinOp::GTE => ">=",
+            BinOp::Equal => "==",
+            BinOp::NotEqual => "!=",
+            BinOp::And => "&&",
+            BinOp::Or => "||",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum UnOp {
+    Neg,
+    Not,
+}
+
+impl UnOp {
+    #[must_use]
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            UnOp::Neg => "-",
+            UnOp::Not => "!",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Type {
     Unit,
     I32,
     F32,
     Function(Vec<Parameter>, Box<Type>),
     Ident(Ident),
+    /// A struct bound to impls of some of its traits, like `Set[Hash = C]`. A value of the type
+    /// always uses those impls, whatever impls are in scope where it is used
+    Indexed(Ident, Vec<ImplBinding>),
+    /// A generic struct applied to type arguments, like `List<T>`
+    Applied(Ident, Vec<Type>),
 }
 
+impl Type {
+    /// The struct or type parameter the type names, if any
+    #[must_use]
+    pub fn ident(&self) -> Option<&Ident> {
+        match self {
+            Type::Ident(ident) | Type::Indexed(ident, _) | Type::Applied(ident, _) => Some(ident),
+            Type::Unit | Type::I32 | Type::F32 | Type::Function(..) => None,
+        }
+    }
+}
+
+/// A type parameter like `T: Ord + Show`, with the traits bounding it
 #[derive(Debug)]
-pub enum Impl {
-    /** Impl name, Trait name, Struct name */
-    Trait(Option<Ident>, Ident, Ident, Vec<Function>),
-    Struct(Ident, Vec<Function>),
+pub struct Generic {
+    pub ident: Ident,
+    pub bounds: Vec<Ident>,
+}
+
+/// `Trait = Impl` in an impl-indexed type
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImplBinding {
+    pub trait_ident: Ident,
+    pub impl_ident: Ident,
+}
+
+impl std::fmt::Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Type::Unit => write!(f, "()"),
+            Type::I32 => write!(f, "i32"),
+            Type::F32 => write!(f, "f32"),
+            Type::Function(parameters, return_type) => {
+                let parameters = parameters
+                    .iter()
+                    .map(|p| p.parameter_type.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                write!(f, "fn({parameters}) -> {return_type}")
+            }
+            Type::Ident(ident) => write!(f, "{ident}"),
+            Type::Indexed(ident, bindings) => {
+                let bindings = bindings
+                    .iter()
+                    .map(|b| format!("{} = {}", b.trait_ident, b.impl_ident))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                write!(f, "{ident}[{bindings}]")
+            }
+            Type::Applied(ident, arguments) => {
+                let arguments = arguments
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                write!(f, "{ident}<{arguments}>")
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Impl<S = Nil> {
+    /** Impl name, type parameters, Trait name, implementing struct type */
+    Trait(Option<Ident>, Vec<Generic>, Ident, Type, Vec<Function<S>>),
+    Struct(Ident, Vec<Function<S>>),
 }
 
 #[derive(Debug)]
-pub struct Function {
+pub struct Function<S = Nil> {
     pub ident: Ident,
+    pub generics: Vec<Generic>,
+    /// The `self` parameter of a method in an impl, if it takes the value it is called on
+    pub receiver: Option<Ident>,
     pub parameters: Vec<Parameter>,
     pub return_type: Option<Type>,
-    pub body: Expr,
+    pub body: Node<Expr<S>, S>,
+    /// Doc comments of a method in an impl. Top-level functions keep theirs on the [`Item`]
+    pub docs: Vec<String>,
+}
+
+impl<S> Function<S> {
+    fn annotate<U, F>(self, f: &mut F) -> Function<Cons<U, S>>
+    where
+        U: Annotation,
+        S: Excludes<U>,
+        F: FnMut(&Node<Expr<S>, S>) -> U,
+    {
+        Function {
+            ident: self.ident,
+            generics: self.generics,
+            receiver: self.receiver,
+            parameters: self.parameters,
+            return_type: self.return_type,
+            body: annotate_node(self.body, f),
+            docs: self.docs,
+        }
+    }
 }
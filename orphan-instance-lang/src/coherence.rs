@@ -0,0 +1,379 @@
+//! Coherence checking.
+//! Unlike Rust, a trait may be implemented several times for the same struct, as long as each
+//! impl has a name a `use` can select it by. This pass collects every trait impl into a table
+//! keyed by trait and struct, rejects anonymous impls that would make a pair ambiguous where both
+//! are visible, and checks that every impl implements exactly the methods its trait declares,
+//! apart from default methods it may leave out. The struct of an impl must also implement every
+//! supertrait of the trait. Finally, it rejects `use` items of a mod selecting different impls
+//! for the same pair.
+
+use crate::ast::{Definition, Function, Ident, Impl, TraitMethod};
+use crate::diagnostic::Diagnostic;
+use crate::resolve::{DefId, DefKind, Resolution};
+use crate::session::Session;
+use crate::typeck::Signature;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+/// Every trait impl of a program, keyed by the trait and the struct it is implemented for
+#[derive(Debug, Default)]
+pub struct ImplTable {
+    impls: HashMap<(DefId, DefId), Vec<DefId>>,
+}
+
+impl ImplTable {
+    /// Impls of `trait_id` for `struct_id`, in source order
+    #[must_use]
+    pub fn impls(&self, trait_id: DefId, struct_id: DefId) -> &[DefId] {
+        self.impls
+            .get(&(trait_id, struct_id))
+            .map_or(&[], Vec::as_slice)
+    }
+
+    /// Every `(trait, struct)` pair with at least one impl
+    pub fn pairs(&self) -> impl Iterator<Item = (DefId, DefId)> + '_ {
+        self.impls.keys().copied()
+    }
+}
+
+/// Builds the impl table of the program, reporting conflicting anonymous impls, impls that do
+/// not match their trait and conflicting `use` items to the session
+pub fn check(session: &mut Session, resolution: &Resolution) -> ImplTable {
+    let mut table = ImplTable::default();
+    // Anonymous impls of each pair
+    let mut anonymous: HashMap<_, Vec<DefId>> = HashMap::new();
+
+    for impl_id in resolution.defs_of_kind(DefKind::Impl) {
+        let def = resolution.def(impl_id);
+        let Definition::Impl(Impl::Trait(name, _, trait_ident, _, functions)) =
+            &def.item.definition
+        else {
+            continue;
+        };
+        // Unresolved names were already reported
+        let Some((trait_id, struct_id)) = resolution.impl_of(impl_id) else {
+            continue;
+        };
+
+        let pair = (trait_id, struct_id);
+        if name.is_none() && conflicts(resolution, impl_id, anonymous.entry(pair).or_default()) {
+            let message = format!(
+                "conflicting anonymous impls of `{}` for `{}`",
+                resolution.path(trait_id),
+                resolution.path(struct_id),
+            );
+            session.emit(Diagnostic::error(message).with_span(trait_ident.span()));
+        }
+        table.impls.entry(pair).or_default().push(impl_id);
+
+        let Definition::Trait(_, _, methods) = &resolution.def(trait_id).item.definition else {
+            continue;
+        };
+        check_methods(
+            session,
+            resolution,
+            trait_id,
+            trait_ident,
+            methods,
+            functions,
+        );
+    }
+
+    // Supertrait impls may come after the impls building on them, so the table must be complete
+    for impl_id in resolution.defs_of_kind(DefKind::Impl) {
+        check_supertraits(session, resolution, &table, impl_id);
+    }
+
+    let modules = resolution.defs_of_kind(DefKind::Mod).map(Some);
+    for module in std::iter::once(None).chain(modules) {
+        check_uses(session, resolution, module);
+    }
+
+    table
+}
+
+/// Whether the anonymous impl `impl_id` and any of the `others` are visible together from one of
+/// their mods, then adds it to them
+fn conflicts(resolution: &Resolution, impl_id: DefId, others: &mut Vec<DefId>) -> bool {
+    let parent = resolution.def(impl_id).parent;
+    let conflict = others.iter().any(|&other| {
+        let other_parent = resolution.def(other).parent;
+        resolution.is_visible(other, parent) || resolution.is_visible(impl_id, other_parent)
+    });
+    others.push(impl_id);
+    conflict
+}
+
+/// Checks that the struct of a trait impl implements every supertrait of the trait. Which of
+/// their impls it builds on is up to [`instance`](crate::instance) resolution
+fn check_supertraits(
+    session: &mut Session,
+    resolution: &Resolution,
+    table: &ImplTable,
+    impl_id: DefId,
+) {
+    let Definition::Impl(Impl::Trait(_, _, trait_ident, ..)) =
+        &resolution.def(impl_id).item.definition
+    else {
+        return;
+    };
+    let Some((trait_id, struct_id)) = resolution.impl_of(impl_id) else {
+        return;
+    };
+    for supertrait in resolution.supertraits(trait_id) {
+        if table.impls(supertrait, struct_id).is_empty() {
+            let message = format!(
+                "impl of `{}` for `{}` requires an impl of supertrait `{}`",
+                resolution.path(trait_id),
+                resolution.path(struct_id),
+                resolution.path(supertrait),
+            );
+            session.emit(Diagnostic::error(message).with_span(trait_ident.span()));
+        }
+    }
+}
+
+/// Checks that the `use` items of a mod select at most one impl of each trait for each struct
+fn check_uses(session: &mut Session, resolution: &Resolution, module: Option<DefId>) {
+    let mut selected = HashMap::new();
+    for ident in resolution.module_uses(module) {
+        let Some(impl_id) = resolution.res_def(ident) else {
+            continue;
+        };
+        let Some(pair) = resolution.impl_of(impl_id) else {
+            continue;
+        };
+        match selected.entry(pair) {
+            Entry::Vacant(entry) => {
+                entry.insert(impl_id);
+            }
+            Entry::Occupied(entry) if *entry.get() != impl_id => {
+                let message = format!(
+                    "`use` of `{}` conflicts with `use` of `{}`, both select an impl of `{}` for `{}`",
+                    resolution.path(impl_id),
+                    resolution.path(*entry.get()),
+                    resolution.path(pair.0),
+                    resolution.path(pair.1),
+                );
+                session.emit(Diagnostic::error(message).with_span(ident.span()));
+            }
+            Entry::Occupied(_) => {}
+        }
+    }
+}
+
+/// Checks that the functions of an impl are exactly the methods of the trait, each defined once
+/// with the same signature. Methods with a default body may be left out
+fn check_methods(
+    session: &mut Session,
+    resolution: &Resolution,
+    trait_id: DefId,
+    trait_ident: &Ident,
+    methods: &[TraitMethod],
+    functions: &[Function],
+) {
+    let trait_path = resolution.path(trait_id);
+
+    for (index, function) in functions.iter().enumerate() {
+        let name = function.ident.name();
+        if functions[..index].iter().any(|f| f.ident.name() == name) {
+            let message = format!("method `{name}` is defined more than once");
+            session.emit(Diagnostic::error(message).with_span(function.ident.span()));
+        }
+    }
+
+    for method in methods {
+        let name = method.ident.name();
+        let Some(function) = functions.iter().find(|f| f.ident.name() == name) else {
+            if method.body.is_some() {
+                continue;
+            }
+            let message = format!("missing method `{name}` of trait `{trait_path}`");
+            session.emit(Diagnostic::error(message).with_span(trait_ident.span()));
+            continue;
+        };
+
+        let expected = Signature::of_method(resolution, method);
+        let mut found = Signature::of_function(resolution, function);
+        if function.return_type.is_none() {
+            // Left out, it is inferred as the declared type and typeck checks the body against it
+            found.output = expected.output.clone();
+        }
+        if !function.generics.is_empty() {
+            // Trait methods declare no type parameters, so no impl of them may either
+            let message = format!(
+                "method `{name}` has {} type parameter{}, but its declaration in trait \
+                 `{trait_path}` has none",
+                function.generics.len(),
+                if function.generics.len() == 1 {
+                    ""
+                } else {
+                    "s"
+                },
+            );
+            session.emit(Diagnostic::error(message).with_span(function.ident.span()));
+        } else if expected != found {
+            let message = format!(
+                "method `{name}` has an incompatible signature for trait `{trait_path}`, \
+                 expected `{}`, found `{}`",
+                expected.display(resolution),
+                found.display(resolution),
+            );
+            session.emit(Diagnostic::error(message).with_span(function.ident.span()));
+        }
+    }
+
+    for function in functions {
+        let name = function.ident.name();
+        if !methods.iter().any(|m| m.ident.name() == name) {
+            let message = format!("method `{name}` is not a member of trait `{trait_path}`");
+            session.emit(Diagnostic::error(message).with_span(function.ident.span()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+    use crate::resolve::resolve;
+
+    const TRAIT: &str = r"
+struct S {}
+trait T {
+    fn foo(a: i32) -> i32
+    fn bar() -> ()
+}
+";
+
+    fn errors(input: &str) -> Vec<String> {
+        let program = parse(&format!("{TRAIT}{input}")).unwrap();
+        let mut diagnostics = Vec::new();
+        let mut session = Session::new(&mut diagnostics);
+        let resolution = resolve(&mut session, &program);
+        check(&mut session, &resolution);
+        diagnostics.into_iter().map(|d| d.message).collect()
+    }
+
+    #[test]
+    fn test_named_impls_coexist() {
+        let input = r"
+impl A of T for S { fn foo(a: i32) -> i32 { a } fn bar() {} }
+impl B of T for S { fn foo(a: i32) -> i32 { 1 } fn bar() -> () {} }
+impl T for S { fn foo(a: i32) -> i32 { 2 } fn bar() {} }
+";
+        assert!(errors(input).is_empty());
+
+        let program = parse(&format!("{TRAIT}{input}")).unwrap();
+        let mut diagnostics = Vec::new();
+        let mut session = Session::new(&mut diagnostics);
+        let resolution = resolve(&mut session, &program);
+        let table = check(&mut session, &resolution);
+
+        let pairs: Vec<_> = table.pairs().collect();
+        assert_eq!(pairs, [(DefId(1), DefId(0))]);
+        let impls: Vec<_> = table
+            .impls(DefId(1), DefId(0))
+            .iter()
+            .map(|&id| resolution.path(id))
+            .collect();
+        assert_eq!(impls, ["A", "B", "{impl}"]);
+    }
+
+    #[test]
+    fn test_conflicting_anonymous_impls() {
+        let input = r"
+impl T for S { fn foo(a: i32) -> i32 { a } fn bar() {} }
+impl T for S { fn foo(a: i32) -> i32 { a } fn bar() {} }
+";
+        assert_eq!(
+            errors(input),
+            ["conflicting anonymous impls of `T` for `S`"]
+        );
+
+        let input = r"
+mod M { impl T for S { fn foo(a: i32) -> i32 { a } fn bar() {} } }
+mod N { impl T for S { fn foo(a: i32) -> i32 { a } fn bar() {} } }
+mod O { pub impl T for S { fn foo(a: i32) -> i32 { a } fn bar() {} } }
+";
+        assert_eq!(
+            errors(input),
+            ["conflicting anonymous impls of `T` for `S`"]
+        );
+    }
+
+    #[test]
+    fn test_conflicting_uses() {
+        let input = r"
+impl A of T for S { fn foo(a: i32) -> i32 { a } fn bar() {} }
+impl B of T for S { fn foo(a: i32) -> i32 { 1 } fn bar() {} }
+use A;
+use A;
+mod M {
+    use B;
+    use A;
+}
+";
+        assert_eq!(
+            errors(input),
+            ["`use` of `A` conflicts with `use` of `B`, both select an impl of `T` for `S`"]
+        );
+    }
+
+    #[test]
+    fn test_duplicate_and_generic_methods() {
+        let input = r"
+impl A of T for S { fn foo(a: i32) -> i32 { a } fn bar() {} fn foo(a: i32) -> i32 { 1 } }
+impl B of T for S { fn foo<U>(a: i32) -> i32 { a } fn bar<U, V>() {} }
+";
+        assert_eq!(
+            errors(input),
+            [
+                "method `foo` is defined more than once",
+                "method `foo` has 1 type parameter, but its declaration in trait `T` has none",
+                "method `bar` has 2 type parameters, but its declaration in trait `T` has none",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_default_methods_and_supertraits() {
+        let input = r"
+trait U: T { fn baz() -> i32 { 1 } fn qux() -> i32 }
+impl A of T for S { fn foo(a: i32) -> i32 { a } fn bar() {} }
+impl B of U for S { fn qux() -> i32 { 2 } }
+impl C of U for S {}
+struct R {}
+impl U for R { fn baz() -> i32 { 3 } fn qux() -> i32 { 4 } }
+";
+        assert_eq!(
+            errors(input),
+            [
+                "missing method `qux` of trait `U`",
+                "impl of `U` for `R` requires an impl of supertrait `T`",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_impl_methods_match_trait() {
+        let input = r"
+impl A of T for S { fn foo(a: f32) -> i32 { 1 } fn baz() {} }
+impl B of T for S { fn foo(a: f32) { a } fn bar() {} }
+impl C of T for S { fn foo(self, a: i32) -> i32 { a } fn bar() {} }
+";
+        assert_eq!(
+            errors(input),
+            [
+                "method `foo` has an incompatible signature for trait `T`, \
+                 expected `fn(i32) -> i32`, found `fn(f32) -> i32`",
+                "missing method `bar` of trait `T`",
+                "method `baz` is not a member of trait `T`",
+                "method `foo` has an incompatible signature for trait `T`, \
+                 expected `fn(i32) -> i32`, found `fn(f32) -> i32`",
+                "method `foo` has an incompatible signature for trait `T`, \
+                 expected `fn(i32) -> i32`, found `fn(self, i32) -> i32`",
+            ]
+        );
+    }
+}
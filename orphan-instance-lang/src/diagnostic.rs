@@ -0,0 +1,335 @@
+//! Diagnostics reported while compiling a program.
+//! Passes never print on their own, they hand every diagnostic to a caller-supplied
+//! [`DiagnosticSink`], which decides whether to collect, render or discard it.
+
+use crate::ast::Span;
+use crate::incremental::TextEdit;
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// The id of the lint reporting the diagnostic, if any
+    pub code: Option<&'static str>,
+    pub message: String,
+    pub span: Option<Span>,
+    /// Other places in the source relevant to the diagnostic
+    pub labels: Vec<Label>,
+    pub suggestions: Vec<Suggestion>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+/// A machine-applicable fix: applying every edit to the source resolves the diagnostic.
+/// Edits do not overlap
+#[derive(Debug, Clone)]
+pub struct Suggestion {
+    pub message: String,
+    pub edits: Vec<TextEdit>,
+}
+
+impl Suggestion {
+    /// Returns `source` with the suggestion applied
+    #[must_use]
+    pub fn apply(&self, source: &str) -> String {
+        let mut edits: Vec<_> = self.edits.iter().collect();
+        edits.sort_by_key(|edit| edit.span.start);
+
+        let mut out = source.to_string();
+        for edit in edits.into_iter().rev() {
+            out.replace_range(edit.span.start..edit.span.end, &edit.text);
+        }
+        out
+    }
+
+    /// The range of the applied source covered by the edits
+    fn applied_span(&self) -> Span {
+        let start = self.edits.iter().map(|e| e.span.start).min().unwrap_or(0);
+        let end = self.edits.iter().map(|e| e.span.end).max().unwrap_or(0);
+        let growth: isize = self
+            .edits
+            .iter()
+            .map(|e| e.text.len().cast_signed() - (e.span.end - e.span.start).cast_signed())
+            .sum();
+        Span::new(start, end.saturating_add_signed(growth))
+    }
+}
+
+impl Diagnostic {
+    pub fn error(message: impl Into<String>) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            code: None,
+            message: message.into(),
+            span: None,
+            labels: Vec::new(),
+            suggestions: Vec::new(),
+        }
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self {
+        Diagnostic {
+            severity: Severity::Warning,
+            code: None,
+            message: message.into(),
+            span: None,
+            labels: Vec::new(),
+            suggestions: Vec::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = Some(span);
+        self
+    }
+
+    #[must_use]
+    pub fn with_code(mut self, code: &'static str) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    #[must_use]
+    pub fn with_label(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+        });
+        self
+    }
+
+    #[must_use]
+    pub fn with_suggestion(mut self, message: impl Into<String>, edits: Vec<TextEdit>) -> Self {
+        self.suggestions.push(Suggestion {
+            message: message.into(),
+            edits,
+        });
+        self
+    }
+
+    #[must_use]
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    /// Renders the diagnostic in a human readable form, quoting the offending line of `source`,
+    /// followed by every label and suggestion
+    #[must_use]
+    pub fn render(&self, source: &str) -> String {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        let mut out = match self.code {
+            Some(code) => format!("{severity}[{code}]: {}\n", self.message),
+            None => format!("{severity}: {}\n", self.message),
+        };
+        if let Some(span) = self.span {
+            snippet(&mut out, source, span);
+        }
+
+        for label in &self.labels {
+            let _ = writeln!(out, "note: {}", label.message);
+            snippet(&mut out, source, label.span);
+        }
+
+        for suggestion in &self.suggestions {
+            let _ = writeln!(out, "help: {}", suggestion.message);
+            let applied = suggestion.apply(source);
+            let span = suggestion.applied_span();
+            let (line, _) = line_col(&applied, span.start);
+            let (last, _) = line_col(&applied, span.end);
+            let width = last.to_string().len();
+            let _ = writeln!(out, "{:width$} |", "");
+            for (index, text) in applied.lines().enumerate().take(last).skip(line - 1) {
+                let _ = writeln!(out, "{:width$} | {text}", index + 1);
+            }
+        }
+
+        out
+    }
+}
+
+/// Quotes the line `span` starts on, underlining the span
+fn snippet(out: &mut String, source: &str, span: Span) {
+    let (line, col) = line_col(source, span.start);
+    let text = source.lines().nth(line - 1).unwrap_or_default();
+    let width = line.to_string().len();
+    let underline = source
+        .get(span.start..span.end)
+        .map_or(0, |spanned| spanned.chars().count())
+        .min(text.chars().count() + 1 - col)
+        .max(1);
+    let _ = writeln!(out, "{:width$}--> {line}:{col}", "");
+    let _ = writeln!(out, "{:width$} |", "");
+    let _ = writeln!(out, "{line} | {text}");
+    let _ = writeln!(
+        out,
+        "{:width$} | {}{}",
+        "",
+        " ".repeat(col - 1),
+        "^".repeat(underline)
+    );
+}
+
+/// Receives every diagnostic and trace message produced by a [`Session`](crate::session::Session)
+pub trait DiagnosticSink {
+    fn emit(&mut self, diagnostic: Diagnostic);
+
+    /// Called with internal trace output when tracing is enabled. Ignored by default
+    fn trace(&mut self, _message: &str) {}
+}
+
+impl DiagnosticSink for Vec<Diagnostic> {
+    fn emit(&mut self, diagnostic: Diagnostic) {
+        self.push(diagnostic);
+    }
+}
+
+/// Converts a byte offset into a 1-based line and column. Columns count characters, not bytes
+#[must_use]
+pub fn line_col(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset.min(source.len())];
+    let line = before.matches('\n').count() + 1;
+    let start = before.rfind('\n').map_or(0, |i| i + 1);
+    let col = before[start..].chars().count() + 1;
+    (line, col)
+}
+
+/// Converts a 1-based line and column into a byte offset, the inverse of [`line_col`].
+/// Returns `None` if the position lies past the end of its line or of the source
+#[must_use]
+pub fn offset(source: &str, line: usize, col: usize) -> Option<usize> {
+    let mut start = 0;
+    for _ in 0..line.checked_sub(1)? {
+        start += source[start..].find('\n')? + 1;
+    }
+    let text = source[start..].lines().next().unwrap_or_default();
+    let mut ends = text.char_indices().map(|(i, _)| i).chain([text.len()]);
+    Some(start + ends.nth(col.checked_sub(1)?)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_col() {
+        let source = "ab\ncd\n\nef";
+        assert_eq!(line_col(source, 0), (1, 1));
+        assert_eq!(line_col(source, 1), (1, 2));
+        assert_eq!(line_col(source, 3), (2, 1));
+        assert_eq!(line_col(source, 7), (4, 1));
+        assert_eq!(line_col(source, 100), (4, 3));
+    }
+
+    #[test]
+    fn test_offset() {
+        let source = "ab\ncd\n\nef";
+        for offset in [0, 1, 3, 7, 9] {
+            let (line, col) = line_col(source, offset);
+            assert_eq!(super::offset(source, line, col), Some(offset));
+        }
+        assert_eq!(super::offset(source, 2, 3), Some(5));
+        assert_eq!(super::offset(source, 2, 4), None);
+        assert_eq!(super::offset(source, 5, 1), None);
+        assert_eq!(super::offset(source, 0, 1), None);
+    }
+
+    #[test]
+    fn test_render() {
+        let source = "fn main() {\n    foo(1)\n}";
+        let diagnostic = Diagnostic::error("unknown function `foo`").with_span(Span::new(16, 19));
+        let expected = "\
+error: unknown function `foo`
+ --> 2:5
+  |
+2 |     foo(1)
+  |     ^^^
+";
+        assert_eq!(diagnostic.render(source), expected);
+    }
+
+    #[test]
+    fn test_render_non_ascii() {
+        let source = "// héllo\nfn é() { foo }";
+        let start = source.find("foo").unwrap();
+        let diagnostic =
+            Diagnostic::error("unknown value `foo`").with_span(Span::new(start, start + 3));
+        let expected = "\
+error: unknown value `foo`
+ --> 2:10
+  |
+2 | fn é() { foo }
+  |          ^^^
+";
+        assert_eq!(diagnostic.render(source), expected);
+        assert_eq!(line_col(source, 7), (1, 7));
+        assert_eq!(offset(source, 2, 10), Some(start));
+    }
+
+    #[test]
+    fn test_render_labels_and_suggestions() {
+        let source = "fn foo() {}\nfn main() {\n    foo(1)\n}";
+        let diagnostic = Diagnostic::error("expected 0 arguments, found 1")
+            .with_span(Span::new(28, 34))
+            .with_label(Span::new(0, 11), "`foo` defined here")
+            .with_suggestion(
+                "remove the argument",
+                vec![TextEdit {
+                    span: Span::new(32, 33),
+                    text: String::new(),
+                }],
+            );
+        let expected = "\
+error: expected 0 arguments, found 1
+ --> 3:5
+  |
+3 |     foo(1)
+  |     ^^^^^^
+note: `foo` defined here
+ --> 1:1
+  |
+1 | fn foo() {}
+  | ^^^^^^^^^^^
+help: remove the argument
+  |
+3 |     foo()
+";
+        assert_eq!(diagnostic.render(source), expected);
+    }
+
+    #[test]
+    fn test_apply_suggestion() {
+        let source = "fn main() {\n    foo(1)\n}";
+        let suggestion = Suggestion {
+            message: "wrap the call".into(),
+            edits: vec![
+                TextEdit {
+                    span: Span::new(22, 22),
+                    text: " }".into(),
+                },
+                TextEdit {
+                    span: Span::new(16, 16),
+                    text: "use A in { ".into(),
+                },
+            ],
+        };
+        assert_eq!(
+            suggestion.apply(source),
+            "fn main() {\n    use A in { foo(1) }\n}"
+        );
+        assert_eq!(suggestion.applied_span(), Span::new(16, 35));
+    }
+}
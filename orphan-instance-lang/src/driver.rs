@@ -0,0 +1,61 @@
+//! Runs the passes following the parser over a program, in order.
+
+use crate::ast::Program;
+use crate::coherence;
+use crate::instance;
+use crate::lint::{self, LintLevels};
+use crate::requirements;
+use crate::resolve::{self, resolve};
+use crate::session::Session;
+use crate::typeck::{self, Typed};
+
+/// Resolves and type checks the program, annotating every expression with its type and scope,
+/// every name with its definition and every trait method call with the impl it uses. Calls
+/// leaving an impl for no caller to pick are errors, like every other error reported to the
+/// session. Returns the annotated program if there were none
+pub fn check(session: &mut Session, program: Program) -> Option<Program<Typed>> {
+    run(session, program, None)
+}
+
+/// Like [`check`], also running the [`lint`] passes at the given levels. Lints denied to errors
+/// also fail it
+pub fn check_and_lint(
+    session: &mut Session,
+    program: Program,
+    levels: &LintLevels,
+) -> Option<Program<Typed>> {
+    run(session, program, Some(levels))
+}
+
+fn run(
+    session: &mut Session,
+    program: Program,
+    levels: Option<&LintLevels>,
+) -> Option<Program<Typed>> {
+    let (names, results, instances) = {
+        let resolution = resolve(session, &program);
+        let table = coherence::check(session, &resolution);
+        let results = typeck::check(session, &resolution, &program);
+        let instances = instance::resolve(&resolution, &table, &results, &program);
+        let requirements = requirements::check(session, &resolution, &table, &instances);
+        if let Some(levels) = levels {
+            lint::check(
+                session,
+                &resolution,
+                &results,
+                &instances,
+                &requirements,
+                levels,
+            );
+        }
+        (resolution.resolved_names(), results, instances)
+    };
+    if session.has_errors() {
+        return None;
+    }
+    let program = instance::annotate(resolve::annotate(program, &names), &instances);
+    let Ok(program) = typeck::annotate(program, results) else {
+        unreachable!("type checking reported no error, so every expression has a type");
+    };
+    Some(program)
+}
@@ -0,0 +1,345 @@
+//! Explanations of instance resolution.
+//! For the call at a position in the source, tells which `(trait, struct)` pair it needs an impl
+//! of, every candidate impl with the rule of [`instance`](crate::instance) resolution that
+//! accepted or rejected it, and the impl finally picked. Trait method calls need an impl for their
+//! receiver, and calls of other functions need one for each [requirement](crate::requirements) of
+//! the function. A call in a default method is explained once for every impl inheriting it.
+
+use crate::ast::{Program, Span};
+use crate::coherence;
+use crate::instance::{self, judge, CallContext, Verdict};
+use crate::requirements;
+use crate::resolve::{resolve, DefId, Resolution};
+use crate::session::Session;
+use crate::typeck;
+use std::fmt;
+
+/// Why a call picked the impl it did
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Explanation {
+    /// The span of the invocation
+    pub span: Span,
+    /// The method or function called
+    pub callee: String,
+    /// The trait impl inheriting the default method making the call, if any
+    pub inheritor: Option<String>,
+    /// The trait an impl is needed of, or several if more than one declares the called method
+    pub traits: Vec<String>,
+    /// The struct an impl is needed for
+    pub struct_path: String,
+    pub candidates: Vec<Candidate>,
+    /// The impl picked, `None` if no rule tells the candidates apart
+    pub choice: Option<String>,
+}
+
+/// An impl a call could use, and the rule deciding whether it does
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    pub path: String,
+    pub verdict: Verdict,
+    /// The verdict in words, naming the impls and mods it refers to
+    pub reason: String,
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let traits: Vec<_> = self.traits.iter().map(|t| format!("`{t}`")).collect();
+        write!(f, "call of `{}`", self.callee)?;
+        if let Some(inheritor) = &self.inheritor {
+            write!(f, " inherited by `{inheritor}`")?;
+        }
+        writeln!(
+            f,
+            " needs an impl of {} for `{}`",
+            traits.join(" or "),
+            self.struct_path
+        )?;
+        for candidate in &self.candidates {
+            writeln!(f, "  `{}`: {}", candidate.path, candidate.reason)?;
+        }
+        match &self.choice {
+            Some(choice) => write!(f, "selected `{choice}`"),
+            None => write!(f, "no impl selected, the call is ambiguous"),
+        }
+    }
+}
+
+/// Explains the innermost call containing `offset` that needs an impl, running every pass over
+/// the program. Calls of functions without requirements are skipped, and a function call with
+/// several requirements gets an explanation for each.
+/// Returns nothing if no such call contains the offset
+#[must_use]
+pub fn explain(program: &Program, offset: usize) -> Vec<Explanation> {
+    let mut diagnostics = Vec::new();
+    let mut session = Session::new(&mut diagnostics);
+    let resolution = resolve(&mut session, program);
+    let table = coherence::check(&mut session, &resolution);
+    let results = typeck::check(&mut session, &resolution, program);
+    let instances = instance::resolve(&resolution, &table, &results, program);
+    let requirements = requirements::check(&mut session, &resolution, &table, &instances);
+
+    let contexts = instances
+        .contexts
+        .iter()
+        .map(|(id, context)| (None, id, context));
+    let inherited = instances
+        .inherited_contexts
+        .iter()
+        .flat_map(|(&inheritor, contexts)| {
+            let contexts = contexts.iter();
+            contexts.map(move |(id, context)| (Some(inheritor), id, context))
+        });
+    let method_calls = contexts.chain(inherited).map(|(inheritor, id, context)| {
+        let call = &results.method_calls[id];
+        let span = results.exprs.span(*id);
+        let candidates = instance::candidates(&resolution, &table, call.receiver, &call.method);
+        let mut traits: Vec<_> = candidates
+            .iter()
+            .filter_map(|&id| resolution.impl_of(id).map(|(trait_id, _)| trait_id))
+            .collect();
+        traits.sort();
+        traits.dedup();
+        let explanation = explanation(
+            &resolution,
+            Query {
+                span,
+                callee: call.method.clone(),
+                inheritor,
+                traits,
+                struct_id: call.receiver,
+                context,
+                bound: &call.bound,
+            },
+            &candidates,
+        );
+        (span, explanation)
+    });
+    let function_calls = instances.calls.iter().flat_map(|call| {
+        let (resolution, table) = (&resolution, &table);
+        requirements.of(call.callee).map(move |requirement| {
+            let candidates = table.impls(requirement.trait_id, requirement.struct_id);
+            let query = Query {
+                span: call.span,
+                callee: resolution.path(call.callee),
+                inheritor: call.inheritor,
+                traits: vec![requirement.trait_id],
+                struct_id: requirement.struct_id,
+                context: &call.context,
+                bound: &[],
+            };
+            (call.span, explanation(resolution, query, candidates))
+        })
+    });
+
+    let mut calls: Vec<_> = method_calls
+        .chain(function_calls)
+        .filter(|(span, _)| span.start <= offset && offset <= span.end)
+        .collect();
+    let Some(innermost) = calls
+        .iter()
+        .map(|(span, _)| *span)
+        .min_by_key(|span| span.end - span.start)
+    else {
+        return Vec::new();
+    };
+    calls.retain(|(span, _)| *span == innermost);
+    let mut explanations: Vec<_> = calls
+        .into_iter()
+        .map(|(_, explanation)| explanation)
+        .collect();
+    // One for each impl inheriting the default method making the call
+    explanations.sort_by(|a, b| a.inheritor.cmp(&b.inheritor));
+    explanations
+}
+
+/// A call needing an impl of one of `traits` for `struct_id`
+struct Query<'a> {
+    span: Span,
+    callee: String,
+    /// The trait impl inheriting the default method making the call, if any
+    inheritor: Option<DefId>,
+    traits: Vec<DefId>,
+    struct_id: DefId,
+    context: &'a CallContext,
+    bound: &'a [DefId],
+}
+
+fn explanation(resolution: &Resolution, query: Query, candidates: &[DefId]) -> Explanation {
+    let verdicts = judge(resolution, query.context, query.bound, candidates);
+    let choice = verdicts
+        .iter()
+        .find(|(_, verdict)| verdict.accepts())
+        .map(|&(id, _)| resolution.path(id));
+    let candidates = verdicts
+        .into_iter()
+        .map(|(id, verdict)| Candidate {
+            path: resolution.path(id),
+            verdict,
+            reason: reason(resolution, query.context, verdict),
+        })
+        .collect();
+
+    Explanation {
+        span: query.span,
+        callee: query.callee,
+        inheritor: query.inheritor.map(|id| resolution.path(id)),
+        traits: query
+            .traits
+            .into_iter()
+            .map(|id| resolution.path(id))
+            .collect(),
+        struct_path: resolution.path(query.struct_id),
+        candidates,
+        choice,
+    }
+}
+
+fn reason(resolution: &Resolution, context: &CallContext, verdict: Verdict) -> String {
+    let module = context.module.map_or_else(
+        || "the top level".to_string(),
+        |id| format!("mod `{}`", resolution.path(id)),
+    );
+    match verdict {
+        Verdict::Bound => "accepted, the type of the receiver is bound to it".into(),
+        Verdict::OtherBound(id) => format!(
+            "rejected, the type of the receiver is bound to `{}`",
+            resolution.path(id)
+        ),
+        Verdict::NotVisible => format!("rejected, not visible from {module}"),
+        Verdict::Used => "accepted, selected by a `use`".into(),
+        Verdict::OtherUsed(id) => {
+            format!("rejected, a `use` selects `{}`", resolution.path(id))
+        }
+        Verdict::Local => format!("accepted, the only candidate in {module}"),
+        Verdict::NotLocal => format!("rejected, not in {module}, unlike other candidates"),
+        Verdict::Unique => "accepted, the only visible candidate".into(),
+        Verdict::Ambiguous => "ambiguous, no rule tells it apart from the others".into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    const SOURCE: &str = r"
+pub struct S {}
+pub trait T { fn foo(self) -> i32 }
+pub impl A of T for S { fn foo(self) -> i32 { 1 } }
+pub impl B of T for S { fn foo(self) -> i32 { 2 } }
+mod M {
+    impl C of T for S { fn foo(self) -> i32 { 3 } }
+    pub fn local(s: S) -> i32 { s.foo() }
+}
+mod N {
+    impl D of T for S { fn foo(self) -> i32 { 4 } }
+}
+fn open(s: S) -> i32 { s.foo() }
+fn main() {
+    let s = S {};
+    use B in open(s);
+    open(s)
+}
+";
+
+    fn explain_at(target: &str) -> Vec<String> {
+        let program = parse(SOURCE).unwrap();
+        let offset = SOURCE.find(target).unwrap();
+        explain(&program, offset)
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn test_explain_method_call() {
+        let expected = "\
+call of `foo` needs an impl of `T` for `S`
+  `A`: rejected, not in mod `M`, unlike other candidates
+  `B`: rejected, not in mod `M`, unlike other candidates
+  `M::C`: accepted, the only candidate in mod `M`
+  `N::D`: rejected, not visible from mod `M`
+selected `M::C`";
+        assert_eq!(explain_at("s.foo() }\n}"), [expected]);
+
+        let expected = "\
+call of `foo` needs an impl of `T` for `S`
+  `A`: ambiguous, no rule tells it apart from the others
+  `B`: ambiguous, no rule tells it apart from the others
+  `M::C`: rejected, not visible from the top level
+  `N::D`: rejected, not visible from the top level
+no impl selected, the call is ambiguous";
+        assert_eq!(explain_at("s.foo() }\nfn main"), [expected]);
+    }
+
+    #[test]
+    fn test_explain_requirement() {
+        let expected = "\
+call of `open` needs an impl of `T` for `S`
+  `A`: rejected, a `use` selects `B`
+  `B`: accepted, selected by a `use`
+  `M::C`: rejected, not visible from the top level
+  `N::D`: rejected, not visible from the top level
+selected `B`";
+        assert_eq!(explain_at("open(s);"), [expected]);
+
+        let explanations = explain_at("open(s)\n");
+        assert_eq!(explanations.len(), 1);
+        assert!(explanations[0].ends_with("no impl selected, the call is ambiguous"));
+    }
+
+    #[test]
+    fn test_explain_interleaved_traits() {
+        let source = r"
+struct S {}
+trait T { fn foo(self) -> i32 }
+trait U { fn foo(self) -> i32 }
+impl A of T for S { fn foo(self) -> i32 { 1 } }
+impl B of U for S { fn foo(self) -> i32 { 2 } }
+impl C of T for S { fn foo(self) -> i32 { 3 } }
+fn main() -> i32 { let s = S {}; s.foo() }
+";
+        let program = parse(source).unwrap();
+        let explanations = explain(&program, source.find("s.foo()").unwrap());
+        assert_eq!(explanations.len(), 1);
+        assert_eq!(explanations[0].traits, ["T", "U"]);
+    }
+
+    #[test]
+    fn test_explain_inherited_default_method() {
+        let source = r"
+struct S {}
+trait Eq { fn eq(self) -> i32 }
+trait Ord: Eq { fn cmp(self, s: S) -> i32 { s.eq() } }
+impl A of Eq for S { fn eq(self) -> i32 { 1 } }
+impl B of Eq for S { fn eq(self) -> i32 { 2 } }
+mod M {
+    use B;
+    pub impl C of Ord for S {}
+}
+impl D of Ord for S {}
+";
+        let program = parse(source).unwrap();
+        let explanations: Vec<_> = explain(&program, source.find("s.eq()").unwrap())
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        let inherited_by_d = "\
+call of `eq` inherited by `D` needs an impl of `Eq` for `S`
+  `A`: ambiguous, no rule tells it apart from the others
+  `B`: ambiguous, no rule tells it apart from the others
+no impl selected, the call is ambiguous";
+        let inherited_by_c = "\
+call of `eq` inherited by `M::C` needs an impl of `Eq` for `S`
+  `A`: rejected, a `use` selects `B`
+  `B`: accepted, selected by a `use`
+selected `B`";
+        assert_eq!(explanations, [inherited_by_d, inherited_by_c]);
+    }
+
+    #[test]
+    fn test_explain_nothing() {
+        assert!(explain_at("let s").is_empty());
+        assert!(explain_at("pub struct").is_empty());
+    }
+}
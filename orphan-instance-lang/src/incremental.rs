@@ -0,0 +1,449 @@
+//! Incremental reparsing for editors.
+//! An edit contained in a single definition only reparses that definition, and splices it into
+//! the existing program. Anything else falls back to parsing the whole source again.
+
+use crate::ast::{Definition, Expr, Function, Generic, Impl, Item, Parameter, Program, Span, Type};
+use crate::node::Node;
+use crate::parser::{check_attributes, oil, parse_with, ParseError};
+use crate::session::Session;
+
+/// Replacement of `span` in the old source with `text`
+#[derive(Debug, Clone)]
+pub struct TextEdit {
+    pub span: Span,
+    pub text: String,
+}
+
+impl TextEdit {
+    /// Maps an offset in the old source to the matching offset in the edited source.
+    /// Offsets inside the replaced range are left as is
+    fn map_offset(&self, offset: usize) -> usize {
+        if offset >= self.span.end {
+            offset - self.span.end + self.span.start + self.text.len()
+        } else {
+            offset
+        }
+    }
+}
+
+/// Reparses `source`, discarding any diagnostics. See [`reparse_with`]
+///
+/// # Errors
+/// Returns [`ParseError`] if the edited source is not a valid program
+pub fn reparse(old: Program, source: &str, edit: &TextEdit) -> Result<Program, ParseError> {
+    let mut diagnostics = Vec::new();
+    reparse_with(&mut Session::new(&mut diagnostics), old, source, edit)
+}
+
+/// Reparses `source`, the result of applying `edit` to the source `old` was parsed from.
+///
+/// If the edit lies strictly inside a definition, only that definition is reparsed, and the spans
+/// of the rest of the program are shifted to match. Otherwise the whole source is parsed again.
+///
+/// # Errors
+/// Returns [`ParseError`] if the edited source is not a valid program.
+/// The reason is emitted to the session
+pub fn reparse_with(
+    session: &mut Session,
+    mut old: Program,
+    source: &str,
+    edit: &TextEdit,
+) -> Result<Program, ParseError> {
+    let Some(path) = enclosing_item(&old.nodes, edit.span) else {
+        session.trace(|| "edit crosses definition boundaries, parsing everything".into());
+        return parse_with(session, source);
+    };
+
+    let target = item_at(&mut old.nodes, &path);
+    let start = target.span.start;
+    let end = edit.map_offset(target.span.end);
+
+    // Warnings are only reported once the definition parses, at offsets in the whole source
+    let mut warnings = Vec::new();
+    let reparsed = source.get(start..end).and_then(|slice| {
+        let mut slice_session = Session::new(&mut warnings);
+        oil::definitionParser::new()
+            .parse(&mut slice_session, slice)
+            .ok()
+    });
+    let Some(mut reparsed) = reparsed else {
+        session.trace(|| "edited definition did not parse on its own, parsing everything".into());
+        return parse_with(session, source);
+    };
+
+    session.trace(|| format!("reparsed definition at {start}..{end}"));
+    for mut warning in warnings {
+        warning.span = warning
+            .span
+            .map(|span| Span::new(span.start + start, span.end + start));
+        session.emit(warning);
+    }
+    map_spans(&mut reparsed, &|offset| offset + start);
+    check_attributes(session, std::slice::from_ref(&reparsed));
+    for item in &mut old.nodes {
+        map_spans(item, &|offset| edit.map_offset(offset));
+    }
+    *item_at(&mut old.nodes, &path) = reparsed;
+
+    Ok(old)
+}
+
+/// Finds the innermost item strictly containing `span`, as a list of indices through nested mods
+fn enclosing_item(items: &[Item], span: Span) -> Option<Vec<usize>> {
+    let index = items
+        .iter()
+        .position(|item| item.span.start < span.start && span.end < item.span.end)?;
+
+    let mut path = vec![index];
+    if let Definition::Mod(_, children) = &items[index].definition {
+        path.extend(enclosing_item(children, span).unwrap_or_default());
+    }
+    Some(path)
+}
+
+fn item_at<'a>(items: &'a mut [Item], path: &[usize]) -> &'a mut Item {
+    let item = &mut items[path[0]];
+    if path.len() == 1 {
+        return item;
+    }
+    match &mut item.definition {
+        Definition::Mod(_, children) => item_at(children, &path[1..]),
+        _ => unreachable!("only mods contain nested items"),
+    }
+}
+
+fn map_spans(item: &mut Item, f: &impl Fn(usize) -> usize) {
+    let map = |span: &mut Span| *span = Span::new(f(span.start), f(span.end));
+    map(&mut item.span);
+    for attribute in &mut item.attributes {
+        map(&mut attribute.span);
+    }
+
+    match &mut item.definition {
+        Definition::Struct(ident, generics, fields) => {
+            map(ident.span_mut());
+            map_generic_spans(generics, &map);
+            for field in fields {
+                map(field.ident.span_mut());
+                map_type_spans(&mut field.field_type, &map);
+            }
+        }
+        Definition::Trait(ident, supertraits, methods) => {
+            map(ident.span_mut());
+            for supertrait in supertraits {
+                map(supertrait.span_mut());
+            }
+            for method in methods {
+                for ident in [&mut method.ident].into_iter().chain(&mut method.receiver) {
+                    map(ident.span_mut());
+                }
+                map_parameter_spans(&mut method.parameters, &map);
+                map_type_spans(&mut method.return_type, &map);
+                if let Some(body) = &mut method.body {
+                    map_expr_spans(body, &map);
+                }
+            }
+        }
+        Definition::Impl(Impl::Trait(name, generics, trait_ident, target, functions)) => {
+            for ident in name.iter_mut().chain([trait_ident]) {
+                map(ident.span_mut());
+            }
+            map_generic_spans(generics, &map);
+            map_type_spans(target, &map);
+            for function in functions {
+                map_function_spans(function, &map);
+            }
+        }
+        Definition::Impl(Impl::Struct(ident, functions)) => {
+            map(ident.span_mut());
+            for function in functions {
+                map_function_spans(function, &map);
+            }
+        }
+        Definition::Mod(ident, children) => {
+            map(ident.span_mut());
+            for child in children {
+                map_spans(child, f);
+            }
+        }
+        Definition::Func(function) => map_function_spans(function, &map),
+        Definition::Use(ident) => map(ident.span_mut()),
+    }
+}
+
+fn map_function_spans(function: &mut Function, map: &impl Fn(&mut Span)) {
+    for ident in [&mut function.ident]
+        .into_iter()
+        .chain(&mut function.receiver)
+    {
+        map(ident.span_mut());
+    }
+    map_generic_spans(&mut function.generics, map);
+    map_parameter_spans(&mut function.parameters, map);
+    if let Some(return_type) = &mut function.return_type {
+        map_type_spans(return_type, map);
+    }
+    map_expr_spans(&mut function.body, map);
+}
+
+fn map_generic_spans(generics: &mut [Generic], map: &impl Fn(&mut Span)) {
+    for generic in generics {
+        map(generic.ident.span_mut());
+        for bound in &mut generic.bounds {
+            map(bound.span_mut());
+        }
+    }
+}
+
+fn map_parameter_spans(parameters: &mut [Parameter], map: &impl Fn(&mut Span)) {
+    for parameter in parameters {
+        map(parameter.ident.span_mut());
+        map_type_spans(&mut parameter.parameter_type, map);
+    }
+}
+
+fn map_type_spans(t: &mut Type, map: &impl Fn(&mut Span)) {
+    match t {
+        Type::Unit | Type::I32 | Type::F32 => {}
+        Type::Function(parameters, return_type) => {
+            map_parameter_spans(parameters, map);
+            map_type_spans(return_type, map);
+        }
+        Type::Ident(ident) => map(ident.span_mut()),
+        Type::Indexed(ident, bindings) => {
+            map(ident.span_mut());
+            for binding in bindings {
+                map(binding.trait_ident.span_mut());
+                map(binding.impl_ident.span_mut());
+            }
+        }
+        Type::Applied(ident, arguments) => {
+            map(ident.span_mut());
+            for argument in arguments {
+                map_type_spans(argument, map);
+            }
+        }
+    }
+}
+
+fn map_expr_spans(expr: &mut Node<Expr>, map: &impl Fn(&mut Span)) {
+    // Nodes not created by the parser have no span to map
+    if let Some(&span) = expr.get_annotation_unchecked::<Span>() {
+        let mut span = span;
+        map(&mut span);
+        expr.add_annotation_unchecked(span);
+    }
+
+    match &mut expr.node {
+        Expr::Literal(_) => {}
+        Expr::Ident(ident) => map(ident.span_mut()),
+        Expr::Paren(e) | Expr::Unary(_, e) => map_expr_spans(e, map),
+        Expr::Binary(left, _, right) => {
+            map_expr_spans(left, map);
+            map_expr_spans(right, map);
+        }
+        Expr::Let(ident, t, value) => {
+            map(ident.span_mut());
+            if let Some(t) = t {
+                map_type_spans(t, map);
+            }
+            map_expr_spans(value, map);
+        }
+        Expr::If(cond, yes, no) => {
+            map_expr_spans(cond, map);
+            map_expr_spans(yes, map);
+            if let Some(no) = no {
+                map_expr_spans(no, map);
+            }
+        }
+        Expr::Block(statements, tail) => {
+            for statement in statements {
+                map_expr_spans(statement, map);
+            }
+            if let Some(tail) = tail {
+                map_expr_spans(tail, map);
+            }
+        }
+        Expr::Invocation(ident, args) => {
+            map(ident.span_mut());
+            for arg in args {
+                map_expr_spans(arg, map);
+            }
+        }
+        Expr::MethodCall(receiver, ident, args) => {
+            map_expr_spans(receiver, map);
+            map(ident.span_mut());
+            for arg in args {
+                map_expr_spans(arg, map);
+            }
+        }
+        Expr::Use(ident, e) => {
+            map(ident.span_mut());
+            map_expr_spans(e, map);
+        }
+        Expr::StructInit(ident, fields) => {
+            map(ident.span_mut());
+            for (field, value) in fields {
+                map(field.span_mut());
+                map_expr_spans(value, map);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostic::{Diagnostic, DiagnosticSink};
+    use crate::parser::parse;
+    use crate::session::ParseOptions;
+
+    const SOURCE: &str = "\
+struct A { a: i32 }
+
+mod M {
+    fn inner() { 1 }
+
+    fn other() { 2 }
+}
+
+/// Entry point
+#[deprecated]
+fn main() { let a = A { a: 1 + 2 }; use M::I in a.b }
+";
+
+    /// Applies the edit and checks the reparsed program matches a full parse of the new source
+    fn assert_reparse(target: &str, replacement: &str) {
+        let start = SOURCE.find(target).unwrap();
+        let edit = TextEdit {
+            span: Span::new(start, start + target.len()),
+            text: replacement.into(),
+        };
+        let source = SOURCE.replacen(target, replacement, 1);
+
+        let old = parse(SOURCE).unwrap();
+        let reparsed = reparse(old, &source, &edit).unwrap();
+        let expected = parse(&source).unwrap();
+
+        assert_eq!(format!("{reparsed:?}"), format!("{expected:?}"));
+    }
+
+    #[test]
+    fn test_reparse_top_level_definition() {
+        assert_reparse("1 + 2", "1 + 2 * 3 - 4");
+        assert_reparse("let a", "let aaa: A");
+        assert_reparse("a: i32", "a: i32, b: f32");
+        assert_reparse("a: i32", "");
+    }
+
+    #[test]
+    fn test_reparse_nested_definition() {
+        assert_reparse("{ 1 }", "{ let x = 10; x }");
+        assert_reparse("{ 2 }", "{ }");
+    }
+
+    #[test]
+    fn test_reparse_only_parses_enclosing_definition() {
+        struct Traces(Vec<String>);
+        impl DiagnosticSink for Traces {
+            fn emit(&mut self, _: Diagnostic) {}
+            fn trace(&mut self, message: &str) {
+                self.0.push(message.into());
+            }
+        }
+
+        let start = SOURCE.find("{ 2 }").unwrap();
+        let edit = TextEdit {
+            span: Span::new(start + 2, start + 3),
+            text: "3".into(),
+        };
+        let source = SOURCE.replacen("{ 2 }", "{ 3 }", 1);
+
+        let mut traces = Traces(Vec::new());
+        let options = ParseOptions { trace: true };
+        let mut session = Session::with_options(options, &mut traces);
+        reparse_with(&mut session, parse(SOURCE).unwrap(), &source, &edit).unwrap();
+
+        let other = source.find("fn other").unwrap();
+        let expected = format!("reparsed definition at {other}..{}", other + 16);
+        assert_eq!(traces.0, [expected]);
+    }
+
+    #[test]
+    fn test_reparse_doc_comment() {
+        assert_reparse("Entry point", "Program entry point");
+        assert_reparse("/ Entry point", " Entry point");
+    }
+
+    #[test]
+    fn test_reparse_attribute() {
+        assert_reparse("#[deprecated]", "#[test]\n#[deprecated(\"old\")]");
+    }
+
+    #[test]
+    fn test_reparse_warns_about_unknown_attributes() {
+        let start = SOURCE.find("{ 2 }").unwrap();
+        let edit = TextEdit {
+            span: Span::new(start - 11, start - 11),
+            text: "#[inline] ".into(),
+        };
+        let source = SOURCE.replacen("fn other", "#[inline] fn other", 1);
+
+        let mut diagnostics = Vec::new();
+        let old = parse(SOURCE).unwrap();
+        reparse_with(&mut Session::new(&mut diagnostics), old, &source, &edit).unwrap();
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].message, "unknown attribute `inline`");
+    }
+
+    #[test]
+    fn test_reparse_warns_about_unused_doc_comments() {
+        let start = SOURCE.find("{ 2 }").unwrap();
+        let edit = TextEdit {
+            span: Span::new(start + 2, start + 2),
+            text: "/// doc\n".into(),
+        };
+        let source = SOURCE.replacen("{ 2 }", "{ /// doc\n2 }", 1);
+
+        let mut diagnostics = Vec::new();
+        let old = parse(SOURCE).unwrap();
+        reparse_with(&mut Session::new(&mut diagnostics), old, &source, &edit).unwrap();
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].message, "unused doc comment");
+        assert_eq!(diagnostics[0].span, Some(Span::new(start + 2, start + 9)));
+    }
+
+    #[test]
+    fn test_reparse_changes_definition_kind() {
+        assert_reparse(
+            "inner() { 1 }",
+            "inner() { 1 }\n    struct B {}\n    fn b() {}",
+        );
+    }
+
+    #[test]
+    fn test_reparse_across_definitions() {
+        assert_reparse("}\n\nmod M {", "}\nstruct B {}\nmod N {");
+        assert_reparse("struct A { a: i32 }\n\n", "");
+        assert_reparse("fn main", "fn main() {}\nfn main2");
+    }
+
+    #[test]
+    fn test_reparse_invalid_edit() {
+        let start = SOURCE.find("1 + 2").unwrap();
+        let edit = TextEdit {
+            span: Span::new(start, start + 5),
+            text: "1 +".into(),
+        };
+        let source = SOURCE.replacen("1 + 2", "1 +", 1);
+
+        let mut diagnostics = Vec::new();
+        let old = parse(SOURCE).unwrap();
+        let result = reparse_with(&mut Session::new(&mut diagnostics), old, &source, &edit);
+
+        assert!(result.is_err());
+        assert_eq!(diagnostics.len(), 1);
+    }
+}
@@ -0,0 +1,1048 @@
+//! Instance resolution.
+//! Several impls of a trait may exist for the same struct, so every call of a trait method has to
+//! pick one. The candidates are the impls, of any trait declaring the method, for the struct the
+//! method is called on. A call uses, in order:
+//!
+//! 1. the candidate named by the innermost enclosing `use`. The `use` items of a mod count as
+//!    enclosing every function of the mod, outside any `use` expression
+//! 2. the only candidate defined in the mod of the calling function
+//! 3. the only candidate in the whole program
+//!
+//! A receiver whose impl-indexed type binds one of the candidates uses that one instead, and an
+//! explicit call like `C::foo()` always uses the impl it names.
+//! Only candidates visible from the calling function count. A call none of these apply to is
+//! ambiguous. Its caller has to pick the impl instead, see [`requirements`](crate::requirements).
+//! [`judge`] tells which rule accepted or rejected each candidate.
+//!
+//! A trait impl also picks, by the same rules, the impl of each supertrait it builds on. Those
+//! apply like a `use` around every method of the impl, including the default methods of the trait
+//! it does not override. A default method is resolved once for every impl inheriting it, as a
+//! method of that impl, and not at all if none does. A call on `self` in a default method uses
+//! the inheriting impl, or the supertrait impl it builds on for the trait declaring the method.
+//!
+//! A call instantiating bounded type parameters picks, by the same rules, an impl of every bound
+//! for the type it instantiates the parameter with. So does a call using a generic impl, like
+//! `impl<T: Show> Show for List<T>`, for the type arguments of the receiver: choosing `Show` for
+//! `List<A>` also chooses `Show` for `A`.
+//!
+//! A `use` expression is needed by the calls it encloses that would pick another impl, or none,
+//! without it. The [`lint`](crate::lint) passes warn about the others.
+
+use crate::ast::{Definition, Expr, Function, Impl, Item, Program, Span};
+use crate::coherence::ImplTable;
+use crate::node::{Cons, Excludes, Node};
+use crate::resolve::{DefId, Res, Resolution};
+use crate::typeck::{impl_arguments, ExprId, ExprIds, MethodCall, Ty, TypeckResults};
+use std::collections::{HashMap, HashSet};
+
+/// The impl a trait method call was resolved to, `None` for other expressions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResolvedImpl(pub Option<DefId>);
+
+/// A trait method call more than one impl could apply to
+#[derive(Debug)]
+pub struct AmbiguousCall {
+    /// The span of the invocation
+    pub span: Span,
+    /// The function containing the call, `None` in impl methods
+    pub caller: Option<DefId>,
+    /// The trait impl inheriting the default method containing the call, if any
+    pub inheritor: Option<DefId>,
+    pub receiver: DefId,
+    pub method: String,
+    pub candidates: Vec<DefId>,
+}
+
+/// A supertrait of a trait impl that more than one impl, or none visible, could be built on
+#[derive(Debug)]
+pub struct AmbiguousSupertrait {
+    /// The trait impl building on the supertrait
+    pub impl_id: DefId,
+    pub supertrait: DefId,
+    pub struct_id: DefId,
+    pub candidates: Vec<DefId>,
+}
+
+/// Where a call is made, which decides the impls it may pick
+#[derive(Debug, Clone, Default)]
+pub struct CallContext {
+    /// The mod of the calling function
+    pub module: Option<DefId>,
+    /// Impls selected by the `use` items and expressions around the call, innermost last
+    pub uses: Vec<DefId>,
+    /// The index in `uses` of the impl selected by each `use` expression, with the span of its
+    /// impl name
+    pub exprs: Vec<(usize, Span)>,
+}
+
+impl CallContext {
+    /// The context without the `use` expression at `index` in `exprs`
+    fn without(&self, index: usize) -> CallContext {
+        let mut context = self.clone();
+        let (removed, _) = context.exprs.remove(index);
+        context.uses.remove(removed);
+        for (position, _) in &mut context.exprs[index..] {
+            *position -= 1;
+        }
+        context
+    }
+}
+
+/// A call of a function, which has to discharge the [`requirements`](crate::requirements) of
+/// the function it calls
+#[derive(Debug)]
+pub struct FunctionCall {
+    /// The span of the invocation
+    pub span: Span,
+    /// The function containing the call, `None` in impl methods
+    pub caller: Option<DefId>,
+    /// The trait impl inheriting the default method containing the call, if any
+    pub inheritor: Option<DefId>,
+    pub callee: DefId,
+    pub context: CallContext,
+}
+
+#[derive(Debug, Default)]
+pub struct Instances {
+    /// The impl picked for each trait method call, keyed by the invocation
+    pub impls: HashMap<ExprId, DefId>,
+    /// The impl picked for each trait method call of the default methods a trait impl inherits,
+    /// keyed by the trait impl, then by the invocation
+    pub inherited: HashMap<DefId, HashMap<ExprId, DefId>>,
+    pub ambiguous: Vec<AmbiguousCall>,
+    pub calls: Vec<FunctionCall>,
+    /// The context of each trait method call, keyed by the invocation
+    pub contexts: HashMap<ExprId, CallContext>,
+    /// The context of each trait method call of the default methods a trait impl inherits, keyed
+    /// by the trait impl, then by the invocation
+    pub inherited_contexts: HashMap<DefId, HashMap<ExprId, CallContext>>,
+    /// The supertrait impls each trait impl builds on
+    pub supertraits: HashMap<DefId, Vec<DefId>>,
+    pub ambiguous_supertraits: Vec<AmbiguousSupertrait>,
+    /// The impls picked for the trait bounds of the type parameters a call instantiates, keyed by
+    /// the invocation
+    pub bound_impls: HashMap<ExprId, Vec<DefId>>,
+    /// Spans of the impl names of the `use` expressions some call needs, see [`needed_uses`]
+    pub needed_uses: HashSet<Span>,
+}
+
+/// Why instance resolution accepted or rejected a candidate impl
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    /// The impl-indexed type of the receiver binds the candidate
+    Bound,
+    /// The type of the receiver binds another candidate
+    OtherBound(DefId),
+    /// Not visible from the calling function
+    NotVisible,
+    /// Selected by the innermost `use` naming a candidate
+    Used,
+    /// Another candidate is selected by a `use`
+    OtherUsed(DefId),
+    /// The only candidate defined in the mod of the calling function
+    Local,
+    /// Defined outside the mod of the calling function, while other candidates are defined in it
+    NotLocal,
+    /// The only visible candidate
+    Unique,
+    /// One of several candidates no rule tells apart
+    Ambiguous,
+}
+
+impl Verdict {
+    #[must_use]
+    pub fn accepts(self) -> bool {
+        matches!(
+            self,
+            Verdict::Bound | Verdict::Used | Verdict::Local | Verdict::Unique
+        )
+    }
+}
+
+/// Applies the rules described in the [module documentation](self) to every candidate of a call
+/// made in `context`, on a receiver whose type binds the impls `bound`
+#[must_use]
+pub fn judge(
+    resolution: &Resolution,
+    context: &CallContext,
+    bound: &[DefId],
+    candidates: &[DefId],
+) -> Vec<(DefId, Verdict)> {
+    let bound = candidates.iter().copied().find(|id| bound.contains(id));
+    let visible: Vec<_> = candidates
+        .iter()
+        .copied()
+        .filter(|&id| resolution.is_visible(id, context.module))
+        .collect();
+    let used = context
+        .uses
+        .iter()
+        .rev()
+        .copied()
+        .find(|id| visible.contains(id));
+    let local: Vec<_> = visible
+        .iter()
+        .copied()
+        .filter(|&id| resolution.def(id).parent == context.module)
+        .collect();
+
+    let verdict = |id| match (bound, used) {
+        (Some(bound), _) if bound == id => Verdict::Bound,
+        (Some(bound), _) => Verdict::OtherBound(bound),
+        _ if !visible.contains(&id) => Verdict::NotVisible,
+        (_, Some(used)) if used == id => Verdict::Used,
+        (_, Some(used)) => Verdict::OtherUsed(used),
+        _ if !local.is_empty() && !local.contains(&id) => Verdict::NotLocal,
+        _ if local.len() == 1 => Verdict::Local,
+        _ if visible.len() == 1 => Verdict::Unique,
+        _ => Verdict::Ambiguous,
+    };
+    candidates.iter().map(|&id| (id, verdict(id))).collect()
+}
+
+/// Picks an impl from `candidates` for a call made in `context`, see [`judge`].
+/// Returns the candidates that could not be told apart if there is no single choice
+pub(crate) fn pick(
+    resolution: &Resolution,
+    context: &CallContext,
+    bound: &[DefId],
+    candidates: &[DefId],
+) -> Result<DefId, Vec<DefId>> {
+    let verdicts = judge(resolution, context, bound, candidates);
+    if let Some(&(id, _)) = verdicts.iter().find(|(_, verdict)| verdict.accepts()) {
+        return Ok(id);
+    }
+    Err(verdicts
+        .into_iter()
+        .filter(|&(_, verdict)| verdict == Verdict::Ambiguous)
+        .map(|(id, _)| id)
+        .collect())
+}
+
+/// Spans of the `use` expressions of `context` the impl [`pick`]ed for a call depends on: without
+/// any of them, the call would pick another impl or none. Of several `use` expressions selecting
+/// the same impl, only the outermost is needed
+pub(crate) fn needed_uses(
+    resolution: &Resolution,
+    context: &CallContext,
+    bound: &[DefId],
+    candidates: &[DefId],
+) -> Vec<Span> {
+    let picked = pick(resolution, context, bound, candidates);
+    let mut context = context.clone();
+    let mut needed = Vec::new();
+    // Innermost first, leaving out those found unneeded when looking at the ones around them
+    for index in (0..context.exprs.len()).rev() {
+        let without = context.without(index);
+        if pick(resolution, &without, bound, candidates) == picked {
+            context = without;
+        } else {
+            needed.push(context.exprs[index].1);
+        }
+    }
+    needed
+}
+
+/// Impls of every trait declaring `method` for the struct `receiver`, in source order
+#[must_use]
+pub fn candidates(
+    resolution: &Resolution,
+    table: &ImplTable,
+    receiver: DefId,
+    method: &str,
+) -> Vec<DefId> {
+    let declares = |trait_id| resolution.trait_method(trait_id, method).is_some();
+    let mut candidates: Vec<_> = table
+        .pairs()
+        .filter(|&(trait_id, struct_id)| struct_id == receiver && declares(trait_id))
+        .flat_map(|(trait_id, struct_id)| table.impls(trait_id, struct_id))
+        .copied()
+        .collect();
+    candidates.sort();
+    candidates
+}
+
+/// Picks the impl of every trait method call found by type checking, and records every function
+/// call for [`requirements`](crate::requirements) to check
+#[must_use]
+pub fn resolve(
+    resolution: &Resolution,
+    table: &ImplTable,
+    results: &TypeckResults,
+    program: &Program,
+) -> Instances {
+    let mut resolver = InstanceResolver {
+        resolution,
+        table,
+        ids: &results.exprs,
+        method_calls: &results.method_calls,
+        self_calls: &results.self_calls,
+        bounds: &results.bounds,
+        module: None,
+        caller: None,
+        inheritor: None,
+        uses: Vec::new(),
+        exprs: Vec::new(),
+        instances: Instances::default(),
+    };
+    resolver.enter_uses(None);
+    resolver.resolve_items(&program.nodes);
+    resolver.instances
+}
+
+/// Attaches a [`ResolvedImpl`] to every expression of the program
+#[must_use]
+pub fn annotate<S>(program: Program<S>, instances: &Instances) -> Program<Cons<ResolvedImpl, S>>
+where
+    S: Excludes<ResolvedImpl>,
+{
+    // Ids number the expressions in the order they are annotated
+    let mut next = 0;
+    program.annotate(&mut |expr| {
+        let id = ExprId(next);
+        next += 1;
+        let call = matches!(expr.node, Expr::Invocation(..) | Expr::MethodCall(..));
+        ResolvedImpl(call.then(|| instances.impls.get(&id).copied()).flatten())
+    })
+}
+
+struct InstanceResolver<'r, 'a> {
+    resolution: &'r Resolution<'a>,
+    table: &'r ImplTable,
+    ids: &'r ExprIds,
+    method_calls: &'r HashMap<ExprId, MethodCall>,
+    self_calls: &'r HashMap<ExprId, Vec<DefId>>,
+    bounds: &'r HashMap<ExprId, Vec<(DefId, Ty)>>,
+    /// The mod of the function being resolved, `None` at the top level
+    module: Option<DefId>,
+    /// The function being resolved, `None` in impl methods
+    caller: Option<DefId>,
+    /// The trait impl inheriting the default method being resolved, if any
+    inheritor: Option<DefId>,
+    /// Impls selected by the enclosing `use` items and expressions, innermost last
+    uses: Vec<DefId>,
+    /// The enclosing `use` expressions, see [`CallContext::exprs`]
+    exprs: Vec<(usize, Span)>,
+    instances: Instances,
+}
+
+impl<'a> InstanceResolver<'_, 'a> {
+    /// Selects the impls of the `use` items of `module`, which apply like a `use` around every
+    /// function of the mod
+    fn enter_uses(&mut self, module: Option<DefId>) {
+        let resolution = self.resolution;
+        let uses = resolution.module_uses(module).iter();
+        self.uses
+            .extend(uses.filter_map(|ident| resolution.res_def(ident)));
+    }
+
+    /// Picks the impl of every supertrait the trait impl `impl_id` builds on, and selects them
+    /// for the methods of the impl
+    fn enter_supertraits(&mut self, impl_id: DefId) {
+        let Some((trait_id, struct_id)) = self.resolution.impl_of(impl_id) else {
+            return;
+        };
+        let context = self.context();
+        for supertrait in self.resolution.supertraits(trait_id) {
+            let candidates = self.table.impls(supertrait, struct_id);
+            // Coherence checking reported supertraits without any impl
+            if candidates.is_empty() {
+                continue;
+            }
+            match pick(self.resolution, &context, &[], candidates) {
+                Ok(id) => {
+                    let built_on = self.instances.supertraits.entry(impl_id).or_default();
+                    built_on.push(id);
+                    self.uses.push(id);
+                }
+                Err(candidates) => self
+                    .instances
+                    .ambiguous_supertraits
+                    .push(AmbiguousSupertrait {
+                        impl_id,
+                        supertrait,
+                        struct_id,
+                        candidates,
+                    }),
+            }
+        }
+    }
+
+    fn resolve_items(&mut self, items: &[Item]) {
+        for item in items {
+            match &item.definition {
+                // Default methods are resolved in every impl inheriting them
+                Definition::Struct(..) | Definition::Use(_) | Definition::Trait(..) => {}
+                Definition::Impl(Impl::Trait(.., functions)) => {
+                    self.caller = None;
+                    let id = self.resolution.item_def(item);
+                    let depth = self.uses.len();
+                    self.enter_supertraits(id);
+                    for function in functions {
+                        self.resolve_expr(&function.body);
+                    }
+                    self.inheritor = Some(id);
+                    for body in self.inherited_defaults(id, functions) {
+                        self.resolve_expr(body);
+                    }
+                    self.inheritor = None;
+                    self.uses.truncate(depth);
+                }
+                Definition::Impl(Impl::Struct(_, functions)) => {
+                    self.caller = None;
+                    for function in functions {
+                        self.resolve_expr(&function.body);
+                    }
+                }
+                Definition::Mod(_, children) => {
+                    let id = self.resolution.item_def(item);
+                    let outer = self.module.replace(id);
+                    let depth = self.uses.len();
+                    self.enter_uses(Some(id));
+                    self.resolve_items(children);
+                    self.uses.truncate(depth);
+                    self.module = outer;
+                }
+                Definition::Func(function) => {
+                    self.caller = Some(self.resolution.item_def(item));
+                    self.resolve_expr(&function.body);
+                }
+            }
+        }
+    }
+
+    /// Bodies of the default methods of its trait the trait impl `impl_id` does not override
+    fn inherited_defaults(&self, impl_id: DefId, functions: &[Function]) -> Vec<&'a Node<Expr>> {
+        let Some((trait_id, _)) = self.resolution.impl_of(impl_id) else {
+            return Vec::new();
+        };
+        let Definition::Trait(.., methods) = &self.resolution.def(trait_id).item.definition else {
+            return Vec::new();
+        };
+        let overridden = |name| functions.iter().any(|f| f.ident.name() == name);
+        methods
+            .iter()
+            .filter(|m| !overridden(m.ident.name()))
+            .filter_map(|m| m.body.as_ref())
+            .collect()
+    }
+
+    fn resolve_expr(&mut self, expr: &Node<Expr>) {
+        match &expr.node {
+            Expr::Use(ident, e) => {
+                let selected = self.resolution.res_def(ident);
+                if let Some(id) = selected {
+                    self.exprs.push((self.uses.len(), ident.span()));
+                    self.uses.push(id);
+                }
+                self.resolve_expr(e);
+                if selected.is_some() {
+                    self.exprs.pop();
+                    self.uses.pop();
+                }
+                return;
+            }
+            Expr::Invocation(ident, _) => {
+                let id = self.ids.id(expr);
+                if let Some(Res::ImplMethod(impl_id)) = self.resolution.res(ident) {
+                    self.instances.impls.insert(id, impl_id);
+                } else if let Some(call) = self.method_calls.get(&id) {
+                    self.resolve_call(id, expr.span(), call);
+                } else if let Some(traits) = self.self_calls.get(&id) {
+                    self.resolve_self_call(id, expr.span(), ident.name(), traits);
+                } else if let Some(callee) = self.resolution.res_def(ident) {
+                    let bounds = self.bounds.get(&id).map_or(&[][..], Vec::as_slice);
+                    for (trait_id, ty) in bounds {
+                        self.resolve_bound(id, expr.span(), ident.name(), *trait_id, ty);
+                    }
+                    self.instances.calls.push(FunctionCall {
+                        span: expr.span(),
+                        caller: self.caller,
+                        inheritor: self.inheritor,
+                        callee,
+                        context: self.context(),
+                    });
+                }
+            }
+            Expr::MethodCall(_, method, _) => {
+                let id = self.ids.id(expr);
+                if let Some(call) = self.method_calls.get(&id) {
+                    self.resolve_call(id, expr.span(), call);
+                } else if let Some(traits) = self.self_calls.get(&id) {
+                    self.resolve_self_call(id, expr.span(), method.name(), traits);
+                }
+            }
+            _ => {}
+        }
+
+        for child in expr.node.children() {
+            self.resolve_expr(child);
+        }
+    }
+
+    fn context(&self) -> CallContext {
+        CallContext {
+            module: self.module,
+            uses: self.uses.clone(),
+            exprs: self.exprs.clone(),
+        }
+    }
+
+    /// Picks an impl for a call made in `context`, see [`pick`], recording the `use` expressions
+    /// it needs
+    fn pick(
+        &mut self,
+        context: &CallContext,
+        bound: &[DefId],
+        candidates: &[DefId],
+    ) -> Result<DefId, Vec<DefId>> {
+        let needed = needed_uses(self.resolution, context, bound, candidates);
+        self.instances.needed_uses.extend(needed);
+        pick(self.resolution, context, bound, candidates)
+    }
+
+    /// Picks the impl of the trait method call `id` at `span`
+    fn resolve_call(&mut self, id: ExprId, span: Span, call: &MethodCall) {
+        let candidates = candidates(self.resolution, self.table, call.receiver, &call.method);
+        let context = self.context();
+        // Type checking rejected any `use` conflicting with the impls the receiver is bound to
+        let picked = self.pick(&context, &call.bound, &candidates);
+        // The call of a default method has a context in every impl inheriting it
+        let contexts = match self.inheritor {
+            Some(inheritor) => self
+                .instances
+                .inherited_contexts
+                .entry(inheritor)
+                .or_default(),
+            None => &mut self.instances.contexts,
+        };
+        contexts.insert(id, context);
+        match picked {
+            Ok(impl_id) => {
+                let impls = match self.inheritor {
+                    Some(inheritor) => self.instances.inherited.entry(inheritor).or_default(),
+                    None => &mut self.instances.impls,
+                };
+                impls.insert(id, impl_id);
+                self.resolve_impl_bounds(id, span, &call.method, impl_id, &call.arguments);
+            }
+            Err(candidates) if candidates.is_empty() => {}
+            Err(candidates) => self.instances.ambiguous.push(AmbiguousCall {
+                span,
+                caller: self.caller,
+                inheritor: self.inheritor,
+                receiver: call.receiver,
+                method: call.method.clone(),
+                candidates,
+            }),
+        }
+    }
+
+    /// Picks the impl of the call `id` at `span` of `method` on `self`, declared by `traits`, for
+    /// the impl inheriting the default method making it: the inheritor itself or an impl it
+    /// builds on, see [`Instances::supertraits`]. Traits further up are picked like for any call
+    fn resolve_self_call(&mut self, id: ExprId, span: Span, method: &str, traits: &[DefId]) {
+        let Some(inheritor) = self.inheritor else {
+            return;
+        };
+        let Some((_, struct_id)) = self.resolution.impl_of(inheritor) else {
+            return;
+        };
+        let mut known = vec![inheritor];
+        known.extend(
+            self.instances
+                .supertraits
+                .get(&inheritor)
+                .into_iter()
+                .flatten(),
+        );
+        let context = self.context();
+        let mut candidates = Vec::new();
+        for &trait_id in traits {
+            let implemented = known
+                .iter()
+                .find(|&&impl_id| self.resolution.impl_of(impl_id) == Some((trait_id, struct_id)));
+            match implemented {
+                Some(&impl_id) => candidates.push(impl_id),
+                None => match self.pick(&context, &[], self.table.impls(trait_id, struct_id)) {
+                    Ok(impl_id) => candidates.push(impl_id),
+                    Err(ambiguous) => candidates.extend(ambiguous),
+                },
+            }
+        }
+        candidates.sort();
+        candidates.dedup();
+        match candidates.as_slice() {
+            [] => {}
+            &[impl_id] => {
+                let inherited = self.instances.inherited.entry(inheritor).or_default();
+                inherited.insert(id, impl_id);
+            }
+            _ => self.instances.ambiguous.push(AmbiguousCall {
+                span,
+                caller: self.caller,
+                inheritor: self.inheritor,
+                receiver: struct_id,
+                method: method.to_string(),
+                candidates,
+            }),
+        }
+    }
+
+    /// Picks an impl of `trait_id` for `ty`, needed by the call `call` of `callee` at `span`. Type
+    /// parameters take their impl from the call instantiating them instead
+    fn resolve_bound(&mut self, call: ExprId, span: Span, callee: &str, trait_id: DefId, ty: &Ty) {
+        let Ty::Struct(struct_id, arguments, bound) = ty else {
+            return;
+        };
+        // Type checking reported types without any impl
+        let candidates = self.table.impls(trait_id, *struct_id);
+        match self.pick(&self.context(), bound, candidates) {
+            Ok(id) => {
+                self.instances.bound_impls.entry(call).or_default().push(id);
+                self.resolve_impl_bounds(call, span, callee, id, arguments);
+            }
+            Err(candidates) if candidates.is_empty() => {}
+            Err(candidates) => self.instances.ambiguous.push(AmbiguousCall {
+                span,
+                caller: self.caller,
+                inheritor: self.inheritor,
+                receiver: *struct_id,
+                method: callee.to_string(),
+                candidates,
+            }),
+        }
+    }
+
+    /// Picks impls for the bounds of the type parameters of the impl `impl_id`, used by a call for
+    /// its struct applied to `arguments`
+    fn resolve_impl_bounds(
+        &mut self,
+        call: ExprId,
+        span: Span,
+        callee: &str,
+        impl_id: DefId,
+        arguments: &[Ty],
+    ) {
+        let Definition::Impl(Impl::Trait(_, generics, _, target, _)) =
+            &self.resolution.def(impl_id).item.definition
+        else {
+            return;
+        };
+        let inferred = impl_arguments(self.resolution, impl_id, target, arguments);
+        for generic in generics {
+            let Some(ty) = inferred.get(&generic.ident.span()) else {
+                continue;
+            };
+            for bound in &generic.bounds {
+                if let Some(trait_id) = self.resolution.res_def(bound) {
+                    self.resolve_bound(call, span, callee, trait_id, ty);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+    use crate::session::Session;
+    use crate::{coherence, typeck};
+
+    /// The id of the outermost expression at `span`
+    fn expr_at(results: &TypeckResults, span: Span) -> ExprId {
+        let mut exprs = results.exprs.iter();
+        exprs.find(|&(_, s)| s == span).unwrap().0
+    }
+
+    /// Runs every pass up to instance resolution, and returns the path of the impl picked for the
+    /// call each `target` starts with, or `None` if it is ambiguous
+    fn picked(input: &str, targets: &[&str]) -> Vec<Option<String>> {
+        let program = parse(input).unwrap();
+        let mut diagnostics = Vec::new();
+        let mut session = Session::new(&mut diagnostics);
+        let resolution = crate::resolve::resolve(&mut session, &program);
+        let table = coherence::check(&mut session, &resolution);
+        let results = typeck::check(&mut session, &resolution, &program);
+        let instances = resolve(&resolution, &table, &results, &program);
+
+        targets
+            .iter()
+            .map(|target| {
+                let start = input.find(target).unwrap();
+                let span = Span::new(start, start + target.find(')').unwrap() + 1);
+                if let Some(call) = instances.ambiguous.iter().find(|c| c.span == span) {
+                    assert!(call.candidates.len() > 1);
+                    return None;
+                }
+                Some(resolution.path(instances.impls[&expr_at(&results, span)]))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_example() {
+        let input = include_str!("../tests/type-error/example.oil");
+        let calls = picked(
+            input,
+            &["a.foo() }\n}\n\n// Now", "a.foo() }\n\n    // This"],
+        );
+        assert_eq!(calls, [Some("D::D".into()), None]);
+    }
+
+    #[test]
+    fn test_use_scopes() {
+        let input = r"
+struct S {}
+trait T { fn foo(self) -> i32 }
+impl A of T for S { fn foo(self) -> i32 { 1 } }
+impl B of T for S { fn foo(self) -> i32 { 2 } }
+mod M {
+    pub impl C of T for S { fn foo(self) -> i32 { 3 } }
+    fn local(s: S) -> i32 { s.foo() }
+    fn outer(s: S) -> i32 { use A in s.foo() }
+}
+fn nested(s: S) -> i32 { use A in { use B in s.foo() } + s.foo() }
+fn ignored(s: S) -> i32 { use M::C in { use X in s.foo() } }
+fn ambiguous(t: S) -> i32 { t.foo() }
+";
+        let calls = picked(
+            input,
+            &[
+                "s.foo() }\n    fn outer",
+                "s.foo() }\n}",
+                "s.foo() } + s.foo() }",
+                "s.foo() }\nfn ignored",
+                "s.foo() } }",
+                "t.foo()",
+            ],
+        );
+        assert_eq!(
+            calls,
+            [
+                Some("M::C".into()),
+                Some("A".into()),
+                Some("B".into()),
+                Some("A".into()),
+                Some("M::C".into()),
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_bound_impls() {
+        let input = r"
+struct S {}
+trait T { fn foo(self) -> i32 }
+impl B of T for S { fn foo(self) -> i32 { 1 } }
+impl C of T for S { fn foo(self) -> i32 { 2 } }
+fn parameter(s: S[T = C]) -> i32 { s.foo() }
+fn built() -> i32 { let t: S[T = B] = S {}; t.foo() }
+fn block(u: S[T = C]) -> i32 { { u }.foo() }
+";
+        let calls = picked(input, &["s.foo()", "t.foo()", "{ u }.foo()"]);
+        assert_eq!(
+            calls,
+            [Some("C".into()), Some("B".into()), Some("C".into())]
+        );
+    }
+
+    #[test]
+    fn test_module_uses() {
+        let input = r"
+struct S {}
+trait T { fn foo(self) -> i32 }
+impl A of T for S { fn foo(self) -> i32 { 1 } }
+impl B of T for S { fn foo(self) -> i32 { 2 } }
+mod M {
+    fn f(s: S) -> i32 { s.foo() }
+    fn g(s: S) -> i32 { use A in s.foo() }
+    use B;
+    mod N {
+        fn h(s: S) -> i32 { s.foo() }
+    }
+}
+fn i(t: S) -> i32 { t.foo() }
+";
+        let calls = picked(
+            input,
+            &[
+                "s.foo() }
+    fn g",
+                "s.foo() }
+    use",
+                "s.foo() }
+    }",
+                "t.foo()",
+            ],
+        );
+        assert_eq!(
+            calls,
+            [Some("B".into()), Some("A".into()), Some("B".into()), None]
+        );
+    }
+
+    #[test]
+    fn test_private_impls() {
+        let input = r"
+struct S {}
+trait T { fn foo(self) -> i32 }
+pub impl A of T for S { fn foo(self) -> i32 { 1 } }
+mod M {
+    impl B of T for S { fn foo(self) -> i32 { 2 } }
+    mod N {
+        fn inner(s: S) -> i32 { s.foo() }
+    }
+}
+fn outer(t: S) -> i32 { t.foo() }
+";
+        let calls = picked(input, &["s.foo()", "t.foo()"]);
+        assert_eq!(calls, [None, Some("A".into())]);
+    }
+
+    #[test]
+    fn test_explicit_impl() {
+        let input = r"
+struct S {}
+trait T { fn foo(self, a: i32) -> i32 }
+impl B of T for S { fn foo(self, a: i32) -> i32 { a } }
+mod M {
+    pub impl C of T for S { fn foo(self, a: i32) -> i32 { a + 1 } }
+}
+fn f(s: S) -> i32 { use B in M::C::foo(s, 1) }
+";
+        assert_eq!(picked(input, &["M::C::foo(s, 1)"]), [Some("M::C".into())]);
+    }
+
+    #[test]
+    fn test_supertrait_impls() {
+        let input = r"
+struct S {}
+trait Eq { fn eq(self) -> i32 }
+trait Ord: Eq { fn cmp(s: S) -> i32 { s.eq() } fn lt(s: S) -> i32 }
+impl A of Eq for S { fn eq(self) -> i32 { 1 } }
+impl B of Eq for S { fn eq(self) -> i32 { 2 } }
+mod M {
+    use B;
+    pub impl C of Ord for S { fn lt(s: S) -> i32 { s.eq() } }
+}
+impl D of Ord for S { fn lt(t: S) -> i32 { t.eq() } }
+";
+        let calls = picked(input, &["s.eq() } }", "t.eq()"]);
+        assert_eq!(calls, [Some("B".into()), None]);
+    }
+
+    #[test]
+    fn test_inherited_default_methods() {
+        let input = r"
+struct S {}
+trait Eq { fn eq(self) -> i32 }
+trait Ord: Eq { fn cmp(s: S) -> i32 { s.eq() } fn lt(s: S) -> i32 { s.eq() } }
+impl A of Eq for S { fn eq(self) -> i32 { 1 } }
+impl B of Eq for S { fn eq(self) -> i32 { 2 } }
+mod M {
+    use B;
+    pub impl C of Ord for S { fn lt(s: S) -> i32 { 3 } }
+}
+impl D of Ord for S {}
+";
+        let program = parse(input).unwrap();
+        let mut diagnostics = Vec::new();
+        let mut session = Session::new(&mut diagnostics);
+        let resolution = crate::resolve::resolve(&mut session, &program);
+        let table = coherence::check(&mut session, &resolution);
+        let results = typeck::check(&mut session, &resolution, &program);
+        let instances = resolve(&resolution, &table, &results, &program);
+        let impl_named = |name| {
+            resolution
+                .defs_of_kind(crate::resolve::DefKind::Impl)
+                .find(|&id| resolution.path(id) == name)
+                .unwrap()
+        };
+
+        let cmp = input.find("s.eq() } fn lt").unwrap();
+        let cmp = Span::new(cmp, cmp + 6);
+        let lt = input.find("s.eq() } }").unwrap();
+        let lt = Span::new(lt, lt + 6);
+        assert!(instances.impls.is_empty());
+        let inherited = &instances.inherited[&impl_named("M::C")];
+        let cmp_call = expr_at(&results, cmp);
+        assert_eq!(inherited.keys().collect::<Vec<_>>(), [&cmp_call]);
+        assert_eq!(resolution.path(inherited[&cmp_call]), "B");
+
+        let spans: Vec<_> = instances.ambiguous.iter().map(|c| c.span).collect();
+        assert_eq!(spans, [cmp, lt]);
+        for call in &instances.ambiguous {
+            assert_eq!(call.inheritor, Some(impl_named("D")));
+        }
+    }
+
+    #[test]
+    fn test_inherited_self_calls() {
+        let input = r"
+struct S {}
+struct R {}
+trait Named { fn name(self) -> i32 }
+trait Greet: Named { fn greet(self) -> i32 { self.name() + self.loud() } fn loud(self) -> i32 }
+trait Shout: Greet { fn shout(self) -> i32 { self.name() } }
+impl N of Named for S { fn name(self) -> i32 { 1 } }
+impl M of Named for R { fn name(self) -> i32 { 2 } }
+impl G of Greet for S { fn loud(self) -> i32 { 3 } }
+impl H of Greet for R { fn loud(self) -> i32 { 4 } }
+impl K of Shout for S {}
+";
+        let program = parse(input).unwrap();
+        let mut diagnostics = Vec::new();
+        let mut session = Session::new(&mut diagnostics);
+        let resolution = crate::resolve::resolve(&mut session, &program);
+        let table = coherence::check(&mut session, &resolution);
+        let results = typeck::check(&mut session, &resolution, &program);
+        let instances = resolve(&resolution, &table, &results, &program);
+        assert!(diagnostics.is_empty());
+        assert!(instances.ambiguous.is_empty());
+
+        let call = |target: &str| {
+            let start = input.find(target).unwrap();
+            expr_at(
+                &results,
+                Span::new(start, start + target.find(')').unwrap() + 1),
+            )
+        };
+        let picked = |inheritor: &str| {
+            let inheritor = resolution
+                .defs_of_kind(crate::resolve::DefKind::Impl)
+                .find(|&id| resolution.path(id) == inheritor)
+                .unwrap();
+            let mut picked: Vec<_> = instances.inherited[&inheritor]
+                .iter()
+                .map(|(&call, &id)| (call, resolution.path(id)))
+                .collect();
+            picked.sort();
+            picked
+        };
+
+        // Each inheritor calls its own impl, or the supertrait impl it builds on
+        let (name, loud) = (call("self.name() +"), call("self.loud()"));
+        assert_eq!(picked("G"), [(name, "N".into()), (loud, "G".into())]);
+        assert_eq!(picked("H"), [(name, "M".into()), (loud, "H".into())]);
+        // A supertrait of a supertrait is picked like for any call
+        assert_eq!(picked("K"), [(call("self.name() }"), "N".into())]);
+    }
+
+    #[test]
+    fn test_bounded_generics() {
+        let input = r"
+struct A {}
+trait Show { fn show(self) -> i32 }
+impl P of Show for A { fn show(self) -> i32 { 1 } }
+impl Q of Show for A { fn show(self) -> i32 { 2 } }
+struct List<T> { head: T }
+impl<T: Show> L of Show for List<T> { fn show(self) -> i32 { 3 } }
+fn shown<T: Show>(t: T) -> i32 { t.show() }
+fn f(l: List<A>) -> i32 { use Q in l.show() }
+fn g(l: List<A>) -> i32 { use P in shown(l) }
+fn h(l: List<A>) -> i32 { shown(l) }
+";
+        let program = parse(input).unwrap();
+        let mut diagnostics = Vec::new();
+        let mut session = Session::new(&mut diagnostics);
+        let resolution = crate::resolve::resolve(&mut session, &program);
+        let table = coherence::check(&mut session, &resolution);
+        let results = typeck::check(&mut session, &resolution, &program);
+        let instances = resolve(&resolution, &table, &results, &program);
+        assert!(diagnostics.is_empty());
+
+        // Every call in the input is 8 bytes long, like `shown(l)`
+        let span = |start: usize| Span::new(start, start + 8);
+        let paths =
+            |ids: &[DefId]| -> Vec<_> { ids.iter().map(|&id| resolution.path(id)).collect() };
+
+        // Choosing `L` for `List<A>` chooses an impl of `Show` for `A` as well
+        let method_call = expr_at(&results, span(input.find("l.show()").unwrap()));
+        assert_eq!(resolution.path(instances.impls[&method_call]), "L");
+        assert_eq!(paths(&instances.bound_impls[&method_call]), ["Q"]);
+        let function_call = expr_at(&results, span(input.find("shown(l)").unwrap()));
+        assert_eq!(paths(&instances.bound_impls[&function_call]), ["L", "P"]);
+
+        let [ambiguous] = instances.ambiguous.as_slice() else {
+            panic!("Expected one ambiguous call");
+        };
+        assert_eq!(ambiguous.span, span(input.rfind("shown(l)").unwrap()));
+        assert_eq!(paths(&ambiguous.candidates), ["P", "Q"]);
+    }
+
+    #[test]
+    fn test_single_impl() {
+        let input = r"
+struct S {}
+trait T { fn foo(self) -> i32 }
+mod M {
+    pub impl C of T for S { fn foo(self) -> i32 { 3 } }
+}
+fn f(s: S) -> i32 { s.foo() }
+";
+        assert_eq!(picked(input, &["s.foo()"]), [Some("M::C".into())]);
+
+        let program = parse(input).unwrap();
+        let mut diagnostics = Vec::new();
+        let program = crate::driver::check(&mut Session::new(&mut diagnostics), program).unwrap();
+        let Definition::Func(function) = &program.nodes[3].definition else {
+            panic!("Expected function");
+        };
+        let Expr::Block(_, Some(call)) = &function.body.node else {
+            panic!("Expected block with tail");
+        };
+        assert_eq!(
+            call.get_annotation::<ResolvedImpl>(),
+            &ResolvedImpl(Some(DefId(3)))
+        );
+    }
+
+    #[test]
+    fn test_unspanned_calls() {
+        let input = r"
+struct S {}
+struct R {}
+trait T { fn foo(self) -> i32 }
+impl A of T for S { fn foo(self) -> i32 { 1 } }
+impl B of T for R { fn foo(self) -> i32 { 2 } }
+fn f(s: S, r: R) -> i32 { s.foo() + r.foo() }
+";
+        let mut program = parse(input).unwrap();
+        let Definition::Func(function) = &mut program.nodes[5].definition else {
+            panic!("Expected function");
+        };
+        let Expr::Block(_, Some(tail)) = &mut function.body.node else {
+            panic!("Expected block with tail");
+        };
+        let Expr::Binary(lhs, _, rhs) = &mut tail.node else {
+            panic!("Expected binary expression");
+        };
+        // Both calls lose the spans telling them apart
+        for call in [lhs, rhs] {
+            **call = Node::from(call.node.clone());
+        }
+
+        let mut diagnostics = Vec::new();
+        let program = crate::driver::check(&mut Session::new(&mut diagnostics), program).unwrap();
+        let Definition::Func(function) = &program.nodes[5].definition else {
+            panic!("Expected function");
+        };
+        let Expr::Block(_, Some(tail)) = &function.body.node else {
+            panic!("Expected block with tail");
+        };
+        let Expr::Binary(lhs, _, rhs) = &tail.node else {
+            panic!("Expected binary expression");
+        };
+        assert_eq!(
+            lhs.get_annotation::<ResolvedImpl>(),
+            &ResolvedImpl(Some(DefId(3)))
+        );
+        assert_eq!(
+            rhs.get_annotation::<ResolvedImpl>(),
+            &ResolvedImpl(Some(DefId(4)))
+        );
+    }
+}
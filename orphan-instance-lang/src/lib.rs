@@ -1,3 +1,15 @@
 pub mod ast;
+pub mod coherence;
+pub mod diagnostic;
+pub mod driver;
+pub mod explain;
+pub mod incremental;
+pub mod instance;
+pub mod lint;
 pub mod node;
+pub mod orphan;
 pub mod parser;
+pub mod requirements;
+pub mod resolve;
+pub mod session;
+pub mod typeck;
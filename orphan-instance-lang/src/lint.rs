@@ -0,0 +1,665 @@
+//! Lints.
+//! Orphan impls make it easy to accumulate code nothing uses. After every other pass, these lints
+//! warn about named impls no call selects, functions `main` never reaches, structs never
+//! constructed and `use` expressions no call needs, see
+//! [`needed_uses`](crate::instance::needed_uses). Programs without `main` or a `#[test]`
+//! function are not checked for unreachable functions.
+//!
+//! Other lints enforce a policy on [orphan](crate::orphan) impls, like requiring them to be
+//! named. Orphans are allowed by default, so these lints are too.
+//!
+//! Every [`Lint`] has an id, like `dead_code`, and a default level. [`LintLevels`] set the level
+//! of a lint for the whole program. `#[allow(id)]`, `#[warn(id)]` and `#[deny(id)]` set it for an
+//! item and everything nested in it, the innermost attribute taking precedence. A denied lint is
+//! an error.
+
+use crate::ast::{AttributeArg, Definition, Expr, Impl, Item};
+use crate::diagnostic::{Diagnostic, Severity};
+use crate::instance::Instances;
+use crate::node::Node;
+use crate::orphan::{self, Orphanhood};
+use crate::requirements::{is_entry, Requirements};
+use crate::resolve::{DefId, DefKind, Resolution};
+use crate::session::Session;
+use crate::typeck::TypeckResults;
+use std::collections::{HashMap, HashSet};
+use std::ptr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Lint {
+    /// A named trait impl no call selects
+    UnusedImpls,
+    /// A function not reachable from `main` or a `#[test]` function
+    DeadCode,
+    /// A struct no expression constructs
+    UnconstructedStructs,
+    /// A `use` expression that does not change the impl any call selects
+    UnusedUses,
+    /// An orphan or fully orphan trait impl
+    OrphanImpls,
+    /// A fully orphan trait impl
+    FullyOrphanImpls,
+    /// An orphan or fully orphan trait impl without a name
+    AnonymousOrphanImpls,
+    /// A fully orphan trait impl without a name
+    AnonymousFullyOrphanImpls,
+}
+
+impl Lint {
+    pub const ALL: [Lint; 8] = [
+        Lint::UnusedImpls,
+        Lint::DeadCode,
+        Lint::UnconstructedStructs,
+        Lint::UnusedUses,
+        Lint::OrphanImpls,
+        Lint::FullyOrphanImpls,
+        Lint::AnonymousOrphanImpls,
+        Lint::AnonymousFullyOrphanImpls,
+    ];
+
+    /// The id naming the lint in attributes and flags
+    #[must_use]
+    pub fn id(self) -> &'static str {
+        match self {
+            Lint::UnusedImpls => "unused_impls",
+            Lint::DeadCode => "dead_code",
+            Lint::UnconstructedStructs => "unconstructed_structs",
+            Lint::UnusedUses => "unused_uses",
+            Lint::OrphanImpls => "orphan_impls",
+            Lint::FullyOrphanImpls => "fully_orphan_impls",
+            Lint::AnonymousOrphanImpls => "anonymous_orphan_impls",
+            Lint::AnonymousFullyOrphanImpls => "anonymous_fully_orphan_impls",
+        }
+    }
+
+    /// The level of the lint unless set otherwise
+    #[must_use]
+    pub fn default_level(self) -> Level {
+        match self {
+            Lint::UnusedImpls | Lint::DeadCode | Lint::UnconstructedStructs | Lint::UnusedUses => {
+                Level::Warn
+            }
+            Lint::OrphanImpls
+            | Lint::FullyOrphanImpls
+            | Lint::AnonymousOrphanImpls
+            | Lint::AnonymousFullyOrphanImpls => Level::Allow,
+        }
+    }
+
+    #[must_use]
+    pub fn from_id(id: &str) -> Option<Lint> {
+        Lint::ALL.into_iter().find(|lint| lint.id() == id)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Allow,
+    Warn,
+    Deny,
+}
+
+impl Level {
+    /// The level set by an attribute named `name`, if it is a lint attribute
+    #[must_use]
+    pub fn from_attribute(name: &str) -> Option<Level> {
+        match name {
+            "allow" => Some(Level::Allow),
+            "warn" => Some(Level::Warn),
+            "deny" => Some(Level::Deny),
+            _ => None,
+        }
+    }
+}
+
+/// The level of every lint for a whole program. Lints without one are at their default level
+#[derive(Debug, Clone, Default)]
+pub struct LintLevels {
+    levels: HashMap<Lint, Level>,
+}
+
+impl LintLevels {
+    pub fn set(&mut self, lint: Lint, level: Level) {
+        self.levels.insert(lint, level);
+    }
+
+    #[must_use]
+    pub fn get(&self, lint: Lint) -> Level {
+        let level = self.levels.get(&lint).copied();
+        level.unwrap_or_else(|| lint.default_level())
+    }
+}
+
+/// Runs every lint over the program, reporting them to the session at their level. Lint
+/// attributes naming unknown lints are warned about too
+pub fn check(
+    session: &mut Session,
+    resolution: &Resolution,
+    results: &TypeckResults,
+    instances: &Instances,
+    requirements: &Requirements,
+    levels: &LintLevels,
+) {
+    check_attributes(session, resolution);
+
+    let mut linter = Linter {
+        resolution,
+        results,
+        instances,
+        requirements,
+        lints: Vec::new(),
+    };
+    linter.unused_impls();
+    linter.dead_code();
+    linter.unconstructed_structs();
+    linter.unused_uses();
+    linter.orphan_impls();
+
+    for (lint, def, mut diagnostic) in linter.lints {
+        match level(resolution, levels, lint, def) {
+            Level::Allow => continue,
+            Level::Warn => {}
+            Level::Deny => diagnostic.severity = Severity::Error,
+        }
+        session.emit(diagnostic.with_code(lint.id()));
+    }
+}
+
+/// The level of `lint` for the definition `def`, set by the innermost lint attribute on it or a
+/// mod around it, or else by `levels`
+fn level(resolution: &Resolution, levels: &LintLevels, lint: Lint, def: DefId) -> Level {
+    let mut current = Some(def);
+    while let Some(id) = current {
+        let item = resolution.def(id).item;
+        // The last attribute naming the lint wins
+        let level = item.attributes.iter().rev().find_map(|attribute| {
+            let level = Level::from_attribute(&attribute.name)?;
+            let names =
+                |arg: &AttributeArg| matches!(arg, AttributeArg::Ident(id) if id == lint.id());
+            attribute.args.iter().any(names).then_some(level)
+        });
+        if let Some(level) = level {
+            return level;
+        }
+        current = resolution.def(id).parent;
+    }
+    levels.get(lint)
+}
+
+/// Warns about lint attributes naming lints that do not exist
+fn check_attributes(session: &mut Session, resolution: &Resolution) {
+    for def in &resolution.defs {
+        for attribute in &def.item.attributes {
+            if Level::from_attribute(&attribute.name).is_none() {
+                continue;
+            }
+            for arg in &attribute.args {
+                let name = match arg {
+                    AttributeArg::Ident(name) if Lint::from_id(name).is_some() => continue,
+                    AttributeArg::Ident(name) | AttributeArg::Str(name) => name,
+                };
+                session.emit(
+                    Diagnostic::warning(format!("unknown lint `{name}`")).with_span(attribute.span),
+                );
+            }
+        }
+    }
+}
+
+struct Linter<'r, 'a> {
+    resolution: &'r Resolution<'a>,
+    results: &'r TypeckResults,
+    instances: &'r Instances,
+    requirements: &'r Requirements,
+    /// Every lint found, with the definition deciding its level
+    lints: Vec<(Lint, DefId, Diagnostic)>,
+}
+
+impl<'a> Linter<'_, 'a> {
+    fn lint(&mut self, lint: Lint, def: DefId, diagnostic: Diagnostic) {
+        self.lints.push((lint, def, diagnostic));
+    }
+
+    fn item(&self, id: DefId) -> &'a Item {
+        self.resolution.def(id).item
+    }
+
+    fn unused_impls(&mut self) {
+        let instances = self.instances;
+        let discharged = self.requirements.discharged.values().flatten();
+        let inherited = instances.inherited.values().flat_map(HashMap::values);
+        let selected: HashSet<DefId> = (instances.impls.values())
+            .chain(inherited)
+            .chain(instances.bound_impls.values().flatten())
+            .chain(instances.supertraits.values().flatten())
+            .chain(discharged.map(|(_, id)| id))
+            .copied()
+            .collect();
+
+        for id in self.resolution.defs_of_kind(DefKind::Impl) {
+            let Definition::Impl(Impl::Trait(Some(name), ..)) = &self.item(id).definition else {
+                continue;
+            };
+            if !selected.contains(&id) {
+                let message = format!("impl `{}` is never selected", self.resolution.path(id));
+                self.lint(
+                    Lint::UnusedImpls,
+                    id,
+                    Diagnostic::warning(message).with_span(name.span()),
+                );
+            }
+        }
+    }
+
+    fn dead_code(&mut self) {
+        let resolution = self.resolution;
+        let functions: Vec<_> = resolution.defs_of_kind(DefKind::Func).collect();
+        let mut reached: HashSet<DefId> = functions
+            .iter()
+            .copied()
+            .filter(|&id| is_entry(resolution, id))
+            .collect();
+        if reached.is_empty() {
+            return;
+        }
+
+        let mut bodies: Vec<_> = reached.iter().filter_map(|&id| self.body(id)).collect();
+        let mut visited = HashSet::new();
+        while let Some(body) = bodies.pop() {
+            if !visited.insert(ptr::from_ref(body)) {
+                continue;
+            }
+            walk(body, &mut |expr| {
+                let ident = match &expr.node {
+                    Expr::Invocation(ident, _) => ident,
+                    Expr::MethodCall(_, method, _) => {
+                        bodies.extend(self.methods(expr, method.name()));
+                        return;
+                    }
+                    _ => return,
+                };
+                let function = resolution
+                    .res_def(ident)
+                    .filter(|&id| resolution.def(id).kind == DefKind::Func);
+                if let Some(id) = function {
+                    reached.insert(id);
+                    bodies.extend(self.body(id));
+                } else {
+                    bodies.extend(self.methods(expr, ident.name()));
+                }
+            });
+        }
+
+        for id in functions {
+            if reached.contains(&id) {
+                continue;
+            }
+            let Definition::Func(function) = &self.item(id).definition else {
+                continue;
+            };
+            let message = format!("function `{}` is never used", resolution.path(id));
+            self.lint(
+                Lint::DeadCode,
+                id,
+                Diagnostic::warning(message).with_span(function.ident.span()),
+            );
+        }
+    }
+
+    /// The body of a function
+    fn body(&self, id: DefId) -> Option<&'a Node<Expr>> {
+        match &self.item(id).definition {
+            Definition::Func(function) => Some(&function.body),
+            _ => None,
+        }
+    }
+
+    /// Bodies of the method `name` the call `call` may run: the one of the impl it was resolved
+    /// to, or else of every impl and trait with a method of that name
+    fn methods(&self, call: &Node<Expr>, name: &str) -> Vec<&'a Node<Expr>> {
+        if let Some(&id) = self.instances.impls.get(&self.results.exprs.id(call)) {
+            return self.impl_method(id, name).into_iter().collect();
+        }
+        let mut bodies = Vec::new();
+        for def in &self.resolution.defs {
+            match &def.item.definition {
+                Definition::Impl(Impl::Trait(.., functions) | Impl::Struct(_, functions)) => {
+                    let named = functions.iter().filter(|f| f.ident.name() == name);
+                    bodies.extend(named.map(|function| &function.body));
+                }
+                Definition::Trait(.., methods) => {
+                    let named = methods.iter().filter(|m| m.ident.name() == name);
+                    bodies.extend(named.filter_map(|method| method.body.as_ref()));
+                }
+                _ => {}
+            }
+        }
+        bodies
+    }
+
+    /// The body of the method `name` of the trait impl `id`, or the default body of its trait
+    fn impl_method(&self, id: DefId, name: &str) -> Option<&'a Node<Expr>> {
+        let Definition::Impl(Impl::Trait(.., functions)) = &self.item(id).definition else {
+            return None;
+        };
+        if let Some(function) = functions.iter().find(|f| f.ident.name() == name) {
+            return Some(&function.body);
+        }
+        let (trait_id, _) = self.resolution.impl_of(id)?;
+        self.resolution.trait_method(trait_id, name)?.body.as_ref()
+    }
+
+    fn unconstructed_structs(&mut self) {
+        let resolution = self.resolution;
+        let mut constructed = HashSet::new();
+        for (_, body) in self.bodies() {
+            walk(body, &mut |expr| {
+                if let Expr::StructInit(ident, _) = &expr.node {
+                    constructed.extend(resolution.res_def(ident));
+                }
+            });
+        }
+
+        for id in resolution.defs_of_kind(DefKind::Struct) {
+            let Definition::Struct(ident, ..) = &self.item(id).definition else {
+                continue;
+            };
+            if !constructed.contains(&id) {
+                let message = format!("struct `{}` is never constructed", resolution.path(id));
+                self.lint(
+                    Lint::UnconstructedStructs,
+                    id,
+                    Diagnostic::warning(message).with_span(ident.span()),
+                );
+            }
+        }
+    }
+
+    fn unused_uses(&mut self) {
+        let resolution = self.resolution;
+        let needed = &self.instances.needed_uses;
+        let discharging = &self.requirements.needed_uses;
+        let mut unused = Vec::new();
+        for (owner, body) in self.bodies() {
+            walk(body, &mut |expr| {
+                let Expr::Use(ident, _) = &expr.node else {
+                    return;
+                };
+                let span = ident.span();
+                // Unresolved names were already reported
+                let Some(id) = resolution.res_def(ident) else {
+                    return;
+                };
+                if !needed.contains(&span) && !discharging.contains(&span) {
+                    let message = format!(
+                        "`use` of `{}` does not change the impl any call selects",
+                        resolution.path(id)
+                    );
+                    unused.push((owner, Diagnostic::warning(message).with_span(span)));
+                }
+            });
+        }
+        for (owner, diagnostic) in unused {
+            self.lint(Lint::UnusedUses, owner, diagnostic);
+        }
+    }
+
+    fn orphan_impls(&mut self) {
+        for origin in orphan::report(self.resolution) {
+            if origin.orphanhood == Orphanhood::Local {
+                continue;
+            }
+            let fully = origin.orphanhood == Orphanhood::FullyOrphan;
+            let anonymous = !origin.is_named(self.resolution);
+            let lints = [
+                Some(Lint::OrphanImpls),
+                fully.then_some(Lint::FullyOrphanImpls),
+                anonymous.then_some(Lint::AnonymousOrphanImpls),
+                (fully && anonymous).then_some(Lint::AnonymousFullyOrphanImpls),
+            ];
+            let message = origin.describe(self.resolution);
+            let span = origin.span(self.resolution);
+            for lint in lints.into_iter().flatten() {
+                let diagnostic = Diagnostic::warning(message.clone()).with_span(span);
+                self.lint(lint, origin.impl_id, diagnostic);
+            }
+        }
+    }
+
+    /// Every function, method and default method body, with the definition it belongs to
+    fn bodies(&self) -> Vec<(DefId, &'a Node<Expr>)> {
+        let mut bodies = Vec::new();
+        for (index, def) in self.resolution.defs.iter().enumerate() {
+            let id = DefId(index);
+            match &def.item.definition {
+                Definition::Func(function) => bodies.push((id, &function.body)),
+                Definition::Impl(Impl::Trait(.., functions) | Impl::Struct(_, functions)) => {
+                    bodies.extend(functions.iter().map(|function| (id, &function.body)));
+                }
+                Definition::Trait(.., methods) => {
+                    let defaults = methods.iter().filter_map(|method| method.body.as_ref());
+                    bodies.extend(defaults.map(|body| (id, body)));
+                }
+                Definition::Struct(..) | Definition::Mod(..) | Definition::Use(_) => {}
+            }
+        }
+        bodies
+    }
+}
+
+/// Calls `f` with `expr` and every expression nested in it
+fn walk<'a>(expr: &'a Node<Expr>, f: &mut impl FnMut(&'a Node<Expr>)) {
+    f(expr);
+    for child in expr.node.children() {
+        walk(child, f);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::driver::check_and_lint;
+    use crate::parser::parse;
+
+    fn lints(input: &str, levels: &LintLevels) -> Vec<(Severity, Option<&'static str>, String)> {
+        let program = parse(input).unwrap();
+        let mut diagnostics = Vec::new();
+        check_and_lint(&mut Session::new(&mut diagnostics), program, levels);
+        diagnostics
+            .into_iter()
+            .map(|d| (d.severity, d.code, d.message))
+            .collect()
+    }
+
+    fn warnings(input: &str) -> Vec<String> {
+        let lints = lints(input, &LintLevels::default());
+        assert!(lints
+            .iter()
+            .all(|(severity, ..)| *severity == Severity::Warning));
+        lints.into_iter().map(|(.., message)| message).collect()
+    }
+
+    const IMPLS: &str = r"
+struct S {}
+trait T { fn foo(self) -> i32 }
+impl A of T for S { fn foo(self) -> i32 { 1 } }
+impl B of T for S { fn foo(self) -> i32 { 2 } }
+";
+
+    #[test]
+    fn test_unused_impls() {
+        let input = format!("{IMPLS}fn main() -> i32 {{ let s = S {{}}; use A in s.foo() }}");
+        assert_eq!(warnings(&input), ["impl `B` is never selected"]);
+
+        // Discharging a requirement selects an impl too
+        let input = format!(
+            "{IMPLS}fn get(s: S) -> i32 {{ s.foo() }}\nfn main() -> i32 {{ use B in get(S {{}}) }}"
+        );
+        assert_eq!(warnings(&input), ["impl `A` is never selected"]);
+    }
+
+    #[test]
+    fn test_dead_code() {
+        let input = format!(
+            r"{IMPLS}
+impl S {{ fn helper(self) -> i32 {{ called() }} }}
+fn called() -> i32 {{ 1 }}
+fn unused() -> i32 {{ recursive() }}
+fn recursive() -> i32 {{ recursive() }}
+fn from_impl() -> i32 {{ 2 }}
+impl C of T for S {{ fn foo(self) -> i32 {{ from_impl() }} }}
+fn main() -> i32 {{ let s = S {{}}; use A in s.foo() + s.helper() }}
+"
+        );
+        assert_eq!(
+            warnings(&input),
+            [
+                "impl `B` is never selected",
+                "impl `C` is never selected",
+                "function `unused` is never used",
+                "function `recursive` is never used",
+                "function `from_impl` is never used",
+            ]
+        );
+
+        // Without an entry point, nothing is reachable
+        let input = "fn f() -> i32 { 1 }";
+        assert!(warnings(input).is_empty());
+    }
+
+    #[test]
+    fn test_unconstructed_structs() {
+        let input = r"
+struct S { a: i32 }
+struct R {}
+mod M { pub struct Q {} }
+fn main() -> i32 { let s = S { a: 1 }; s.a }
+fn f(r: R, q: M::Q) {}
+";
+        assert_eq!(
+            warnings(input),
+            [
+                "function `f` is never used",
+                "struct `R` is never constructed",
+                "struct `M::Q` is never constructed",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unused_uses() {
+        let input = format!(
+            r"{IMPLS}
+mod M {{ pub impl C of T for S {{ fn foo(self) -> i32 {{ 3 }} }} }}
+fn get(s: S) -> i32 {{ s.foo() }}
+fn nested(s: S) -> i32 {{ use A in {{ use A in s.foo() }} }}
+fn outer(s: S) -> i32 {{ use B in {{ use A in get(s) }} }}
+fn local(s: S) -> i32 {{ use M::C in s.foo() }}
+fn constant() -> i32 {{ use A in 1 }}
+fn main() -> i32 {{ let s = S {{}}; nested(s) + outer(s) + local(s) + constant() }}
+"
+        );
+        assert_eq!(
+            warnings(&input),
+            [
+                "impl `B` is never selected",
+                "`use` of `A` does not change the impl any call selects",
+                "`use` of `B` does not change the impl any call selects",
+                "`use` of `A` does not change the impl any call selects",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_orphan_policies() {
+        let input = r"
+pub struct S {}
+pub trait T { fn foo(self) -> i32 }
+#[deny(anonymous_orphan_impls)]
+mod M {
+    pub struct R {}
+    impl T for R { fn foo(self) -> i32 { 1 } }
+    pub impl A of T for S { fn foo(self) -> i32 { 2 } }
+}
+#[deny(anonymous_fully_orphan_impls)]
+mod N {
+    pub struct Q {}
+    impl T for Q { fn foo(self) -> i32 { 3 } }
+    impl T for S { fn foo(self) -> i32 { 4 } }
+}
+mod O { impl T for S { fn foo(self) -> i32 { 5 } } }
+";
+        let mut levels = LintLevels::default();
+        levels.set(Lint::UnconstructedStructs, Level::Allow);
+        let errors: Vec<_> = lints(input, &levels)
+            .into_iter()
+            .filter(|(severity, ..)| *severity == Severity::Error)
+            .map(|(_, code, message)| (code.unwrap(), message))
+            .collect();
+        let error = |code, message: &str| (code, message.to_string());
+        assert_eq!(
+            errors,
+            [
+                error(
+                    "anonymous_orphan_impls",
+                    "anonymous impl of `T` for `M::R` is an orphan, `T` is defined at the top level"
+                ),
+                error(
+                    "anonymous_fully_orphan_impls",
+                    "anonymous impl of `T` for `S` is fully orphan, `T` is defined at the top \
+                     level and `S` is defined at the top level"
+                ),
+            ]
+        );
+
+        levels.set(Lint::FullyOrphanImpls, Level::Warn);
+        let warnings: Vec<_> = lints(input, &levels)
+            .into_iter()
+            .filter_map(|(_, code, _)| code)
+            .collect();
+        assert_eq!(
+            warnings,
+            [
+                "unused_impls",
+                "anonymous_orphan_impls",
+                "fully_orphan_impls",
+                "fully_orphan_impls",
+                "anonymous_fully_orphan_impls",
+                "fully_orphan_impls",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lint_levels() {
+        let input = r"
+#[deny(unconstructed_structs)]
+struct R {}
+#[allow(dead_code)]
+mod M {
+    fn f() {}
+    #[warn(dead_code)]
+    fn g() {}
+}
+#[allow(dead_code, unused_lint)]
+fn h() {}
+struct Q {}
+fn main() {}
+";
+        let mut levels = LintLevels::default();
+        levels.set(Lint::UnconstructedStructs, Level::Allow);
+        let warning = |code, message: &str| (Severity::Warning, code, message.to_string());
+        assert_eq!(
+            lints(input, &levels),
+            [
+                warning(None, "unknown lint `unused_lint`"),
+                warning(Some("dead_code"), "function `M::g` is never used"),
+                (
+                    Severity::Error,
+                    Some("unconstructed_structs"),
+                    "struct `R` is never constructed".to_string()
+                ),
+            ]
+        );
+    }
+}
@@ -1,3 +1,164 @@
-fn main() {
-    println!("Hello, world!");
+use clap::{Parser, Subcommand};
+use orphan_instance_lang::diagnostic::{line_col, offset};
+use orphan_instance_lang::driver::check_and_lint;
+use orphan_instance_lang::explain::explain;
+use orphan_instance_lang::lint::{Level, Lint, LintLevels};
+use orphan_instance_lang::orphan;
+use orphan_instance_lang::parser::parse_with;
+use orphan_instance_lang::resolve::resolve;
+use orphan_instance_lang::session::Session;
+use std::process::ExitCode;
+
+/// The Orphan Instance Language
+#[derive(Parser)]
+#[command(name = "oil")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Check a program, reporting its errors and lints
+    Check {
+        /// The program to check
+        path: String,
+        /// Allow a lint, silencing it
+        #[arg(short = 'A', long = "allow", value_name = "LINT")]
+        allow: Vec<String>,
+        /// Warn about a lint
+        #[arg(short = 'W', long = "warn", value_name = "LINT")]
+        warn: Vec<String>,
+        /// Deny a lint, making it an error. Takes precedence over the other flags
+        #[arg(short = 'D', long = "deny", value_name = "LINT")]
+        deny: Vec<String>,
+    },
+    /// Explain which impl the call at a position uses, and why
+    ExplainImpl {
+        /// Position of the call, as `FILE:LINE:COL`
+        location: String,
+    },
+    /// List every trait impl as local, orphan or fully orphan
+    Orphans {
+        /// The program to report on
+        path: String,
+    },
+}
+
+fn main() -> ExitCode {
+    let result = match Cli::parse().command {
+        Command::Check {
+            path,
+            allow,
+            warn,
+            deny,
+        } => lint_levels(&allow, &warn, &deny).and_then(|levels| check(&path, &levels)),
+        Command::ExplainImpl { location } => explain_impl(&location),
+        Command::Orphans { path } => orphans(&path),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("error: {message}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// The levels set by the lint flags, applying `allow`, then `warn`, then `deny`
+fn lint_levels(allow: &[String], warn: &[String], deny: &[String]) -> Result<LintLevels, String> {
+    let mut levels = LintLevels::default();
+    for (ids, level) in [
+        (allow, Level::Allow),
+        (warn, Level::Warn),
+        (deny, Level::Deny),
+    ] {
+        for id in ids {
+            let lint = Lint::from_id(id).ok_or_else(|| format!("unknown lint `{id}`"))?;
+            levels.set(lint, level);
+        }
+    }
+    Ok(levels)
+}
+
+fn check(path: &str, levels: &LintLevels) -> Result<(), String> {
+    let source =
+        std::fs::read_to_string(path).map_err(|error| format!("cannot read `{path}`: {error}"))?;
+
+    let mut diagnostics = Vec::new();
+    let errors = {
+        let mut session = Session::new(&mut diagnostics);
+        if let Ok(program) = parse_with(&mut session, &source) {
+            check_and_lint(&mut session, program, levels);
+        }
+        session.error_count()
+    };
+    for diagnostic in &diagnostics {
+        eprint!("{}", diagnostic.render(&source));
+    }
+    match errors {
+        0 => Ok(()),
+        1 => Err(format!("could not check `{path}` due to 1 previous error")),
+        n => Err(format!(
+            "could not check `{path}` due to {n} previous errors"
+        )),
+    }
+}
+
+fn explain_impl(location: &str) -> Result<(), String> {
+    let mut parts = location.rsplitn(3, ':');
+    let (Some(col), Some(line), Some(path)) = (parts.next(), parts.next(), parts.next()) else {
+        return Err(format!("expected `FILE:LINE:COL`, found `{location}`"));
+    };
+    let (Ok(line), Ok(col)) = (line.parse(), col.parse()) else {
+        return Err(format!("expected `FILE:LINE:COL`, found `{location}`"));
+    };
+
+    let source =
+        std::fs::read_to_string(path).map_err(|error| format!("cannot read `{path}`: {error}"))?;
+    let offset = offset(&source, line, col)
+        .ok_or_else(|| format!("`{path}` has no line {line} with a column {col}"))?;
+
+    let mut diagnostics = Vec::new();
+    let program = parse_with(&mut Session::new(&mut diagnostics), &source);
+    for diagnostic in &diagnostics {
+        eprint!("{}", diagnostic.render(&source));
+    }
+    let program = program.map_err(|_| format!("could not parse `{path}`"))?;
+
+    let explanations = explain(&program, offset);
+    if explanations.is_empty() {
+        return Err(format!("no call needing an impl at {location}"));
+    }
+    for explanation in explanations {
+        let (line, col) = line_col(&source, explanation.span.start);
+        println!("{path}:{line}:{col}: {explanation}");
+    }
+    Ok(())
+}
+
+fn orphans(path: &str) -> Result<(), String> {
+    let source =
+        std::fs::read_to_string(path).map_err(|error| format!("cannot read `{path}`: {error}"))?;
+
+    let mut diagnostics = Vec::new();
+    let mut session = Session::new(&mut diagnostics);
+    let program = parse_with(&mut session, &source);
+    let report = program.as_ref().ok().map(|program| {
+        let resolution = resolve(&mut session, program);
+        orphan::report(&resolution)
+            .iter()
+            .map(|origin| (origin.span(&resolution), origin.describe(&resolution)))
+            .collect::<Vec<_>>()
+    });
+    for diagnostic in &diagnostics {
+        eprint!("{}", diagnostic.render(&source));
+    }
+    let report = report.ok_or_else(|| format!("could not parse `{path}`"))?;
+
+    for (span, description) in report {
+        let (line, col) = line_col(&source, span.start);
+        println!("{path}:{line}:{col}: {description}");
+    }
+    Ok(())
 }
@@ -2,28 +2,200 @@
 //! It exposes an annotation system which strictly enforces type safety on existing annotations,
 //! only allowing get / set operations with preconfigured types
 
-use crate::ast::Type;
+use crate::ast::{Span, Type};
+use crate::instance::ResolvedImpl;
+use crate::resolve::{ResolvedName, Scope};
 use std::{
     any::{Any, TypeId},
     collections::HashMap,
+    fmt,
     marker::PhantomData,
 };
 use typelist::typelist;
 
-pub trait Annotation: 'static {}
+/// A value nodes can be annotated with
+pub trait Annotation: Any {
+    /// The field name and value a node's `Debug` output shows the annotation as, if any
+    fn debug_field(&self) -> Option<(&'static str, &dyn fmt::Debug)> {
+        None
+    }
+}
+
+/// An annotation nodes can be cloned and compared along with. Nodes in a state of only such
+/// annotations are `Clone` and `PartialEq`, see [`CloneState`]
+pub trait CloneAnnotation: Annotation + Clone + PartialEq {}
+
+/// The boxed annotations of a node, which can be taken back to their type
+trait DynAnnotation: Annotation {
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
+}
+
+impl<U: Annotation> DynAnnotation for U {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+}
+
+// Deep enough for a node to carry every annotation checked but its `Span`, which the parser adds
+// unchecked so that `span()` stays available in every state
+typelist!(4, Type, Span, ResolvedName, ResolvedImpl, Scope);
+
+impl Annotation for Type {
+    fn debug_field(&self) -> Option<(&'static str, &dyn fmt::Debug)> {
+        Some(("type", self))
+    }
+}
+
+impl Annotation for Span {
+    fn debug_field(&self) -> Option<(&'static str, &dyn fmt::Debug)> {
+        Some(("span", self))
+    }
+}
+
+impl Annotation for ResolvedName {
+    fn debug_field(&self) -> Option<(&'static str, &dyn fmt::Debug)> {
+        Some(("name", &self.0))
+    }
+}
+
+impl Annotation for ResolvedImpl {
+    fn debug_field(&self) -> Option<(&'static str, &dyn fmt::Debug)> {
+        Some(("impl", &self.0))
+    }
+}
+
+impl Annotation for Scope {
+    fn debug_field(&self) -> Option<(&'static str, &dyn fmt::Debug)> {
+        Some(("scope", &self.0))
+    }
+}
+
+impl CloneAnnotation for Type {}
+impl CloneAnnotation for Span {}
+impl CloneAnnotation for ResolvedName {}
+impl CloneAnnotation for ResolvedImpl {}
+impl CloneAnnotation for Scope {}
 
-// struct Type;
-typelist!(1, Type);
+/// Annotation states of [`CloneAnnotation`]s only, the nodes in which can be cloned and compared
+pub trait CloneState {
+    /// Copies the annotations of the state from `from` to `to`
+    fn clone_annotations<T, S>(from: &Node<T, S>, to: &mut Node<T, S>);
 
-impl Annotation for Type {}
+    /// Whether `a` and `b` have the same annotations of the state
+    fn eq_annotations<T, S>(a: &Node<T, S>, b: &Node<T, S>) -> bool;
+}
+
+impl CloneState for Nil {
+    fn clone_annotations<T, S>(_: &Node<T, S>, _: &mut Node<T, S>) {}
+
+    fn eq_annotations<T, S>(_: &Node<T, S>, _: &Node<T, S>) -> bool {
+        true
+    }
+}
+
+impl<H: CloneAnnotation, R: CloneState> CloneState for Cons<H, R> {
+    fn clone_annotations<T, S>(from: &Node<T, S>, to: &mut Node<T, S>) {
+        if let Some(annotation) = from.annotation::<H>() {
+            to.insert(annotation.clone());
+        }
+        R::clone_annotations(from, to);
+    }
+
+    fn eq_annotations<T, S>(a: &Node<T, S>, b: &Node<T, S>) -> bool {
+        a.annotation::<H>() == b.annotation::<H>() && R::eq_annotations(a, b)
+    }
+}
+
+// Annotation states are only markers, but the AST types generic over them derive `Debug`
+impl fmt::Debug for Nil {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Nil")
+    }
+}
+
+impl<H, T> fmt::Debug for Cons<H, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Cons")
+    }
+}
+
+/// Marks the annotation [`Remove`] takes out of a state as its head
+pub struct Here;
+
+/// Marks the annotation [`Remove`] takes out of a state as in its tail, at the position `I`
+pub struct There<I>(PhantomData<I>);
+
+/// States including the annotation `U` at the position `I`, which the compiler infers
+pub trait Remove<U, I> {
+    /// The state without `U`
+    type Output;
+}
+
+impl<U, T> Remove<U, Here> for Cons<U, T> {
+    type Output = T;
+}
+
+impl<U, I, H, T: Remove<U, I>> Remove<U, There<I>> for Cons<H, T> {
+    type Output = Cons<H, T::Output>;
+}
 
-#[derive(Debug)]
 pub struct Node<T, S = Nil> {
     pub node: T,
-    annotations: HashMap<TypeId, Box<dyn Any>>,
+    annotations: HashMap<TypeId, Box<dyn DynAnnotation>>,
     _state: PhantomData<S>,
 }
 
+/// Prints the wrapped value followed by the [`Annotation::debug_field`] of every annotation present
+/// on the node, by field name
+impl<T: fmt::Debug, S> fmt::Debug for Node<T, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut fields: Vec<_> = self
+            .annotations
+            .values()
+            .filter_map(|annotation| annotation.debug_field())
+            .collect();
+        fields.sort_by_key(|&(name, _)| name);
+        let mut debug = f.debug_struct("Node");
+        debug.field("node", &self.node);
+        for (name, value) in fields {
+            debug.field(name, value);
+        }
+        debug.finish_non_exhaustive()
+    }
+}
+
+/// Clones the value with the span and the annotations of the state. Other annotations added
+/// unchecked are left out
+impl<T: Clone, S: CloneState> Clone for Node<T, S> {
+    fn clone(&self) -> Self {
+        let mut node = Node::new(self.node.clone());
+        if let Some(&span) = self.annotation::<Span>() {
+            node.insert(span);
+        }
+        S::clone_annotations(self, &mut node);
+        node
+    }
+}
+
+/// Nodes are equal if their values, spans and the annotations of their state are
+impl<T: PartialEq, S: CloneState> PartialEq for Node<T, S> {
+    fn eq(&self, other: &Self) -> bool {
+        self.node == other.node
+            && self.annotation::<Span>() == other.annotation::<Span>()
+            && S::eq_annotations(self, other)
+    }
+}
+
 impl<T> From<T> for Node<T> {
     fn from(value: T) -> Self {
         Node::new(value)
@@ -36,6 +208,15 @@ impl<T> From<T> for Box<Node<T>> {
     }
 }
 
+impl<T> Node<T> {
+    /// Wraps `value` in a node annotated with the source range it was parsed from
+    pub fn with_span(value: T, span: Span) -> Self {
+        let mut node = Node::new(value);
+        node.add_annotation_unchecked(span);
+        node
+    }
+}
+
 impl<T, S> Node<T, S> {
     pub fn new(value: T) -> Self {
         Node {
@@ -45,6 +226,19 @@ impl<T, S> Node<T, S> {
         }
     }
 
+    /// Looks up an annotation regardless of the node's signature
+    fn annotation<U: Annotation>(&self) -> Option<&U> {
+        self.annotations
+            .get(&TypeId::of::<U>())
+            .and_then(|boxed| boxed.as_any().downcast_ref::<U>())
+    }
+
+    /// Sets an annotation regardless of the node's signature
+    fn insert<U: Annotation>(&mut self, annotation: U) {
+        self.annotations
+            .insert(TypeId::of::<U>(), Box::new(annotation));
+    }
+
     /// Adds the provided annotation to the node, and returns a new node with the updated signature
     /// This invalidates the old node, as it is impossible to retrieve a value not included in the
     /// node's signature. Only the new node should be used from then on.
@@ -62,6 +256,38 @@ impl<T, S> Node<T, S> {
         }
     }
 
+    /// Removes an annotation, returning it and the node with the updated signature. The position
+    /// `I` of the annotation in the signature is inferred, as in `node.remove_annotation::<Type, _>()`
+    ///
+    /// # Panics
+    pub fn remove_annotation<U, I>(mut self) -> (Node<T, S::Output>, U)
+    where
+        U: Annotation,
+        S: Remove<U, I>,
+    {
+        let annotation = self
+            .annotations
+            .remove(&TypeId::of::<U>())
+            .and_then(|boxed| boxed.into_any().downcast::<U>().ok());
+        let annotation = annotation
+            .expect("Annotation did not exist, which should have been guaranteed by type system");
+        let node = Node {
+            node: self.node,
+            annotations: self.annotations,
+            _state: PhantomData,
+        };
+        (node, *annotation)
+    }
+
+    /// Replaces the wrapped value with `f` of it, keeping every annotation of the node
+    pub fn map_node<V>(self, f: impl FnOnce(T) -> V) -> Node<V, S> {
+        Node {
+            node: f(self.node),
+            annotations: self.annotations,
+            _state: PhantomData,
+        }
+    }
+
     pub fn add_annotation_unchecked<U>(&mut self, annotation: U)
     where
         U: Annotation,
@@ -89,7 +315,21 @@ impl<T, S> Node<T, S> {
         let annotation = self
             .annotations
             .get(&TypeId::of::<U>())
-            .and_then(|boxed| boxed.downcast_ref::<U>());
+            .and_then(|boxed| boxed.as_any().downcast_ref::<U>());
+        annotation
+            .expect("Annotation did not exist, which should have been guaranteed by type system")
+    }
+
+    /// # Panics
+    pub fn get_annotation_mut<U>(&mut self) -> &mut U
+    where
+        U: Annotation,
+        S: Includes<U>,
+    {
+        let annotation = self
+            .annotations
+            .get_mut(&TypeId::of::<U>())
+            .and_then(|boxed| boxed.as_any_mut().downcast_mut::<U>());
         annotation
             .expect("Annotation did not exist, which should have been guaranteed by type system")
     }
@@ -101,13 +341,27 @@ impl<T, S> Node<T, S> {
     {
         self.annotations
             .get(&TypeId::of::<U>())
-            .and_then(|boxed| boxed.downcast_ref::<U>())
+            .and_then(|boxed| boxed.as_any().downcast_ref::<U>())
+    }
+}
+
+impl<T, S> Node<T, S>
+where
+    S: Excludes<Span>,
+{
+    /// The source range of the node. Nodes not created by the parser have an empty span
+    #[must_use]
+    pub fn span(&self) -> Span {
+        self.get_annotation_unchecked::<Span>()
+            .copied()
+            .unwrap_or_default()
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::resolve::DefId;
 
     #[test]
     fn test_add_get_annotation() {
@@ -116,4 +370,39 @@ mod test {
         let _: &Type = node.get_annotation();
         node.change_annotation(Type::F32);
     }
+
+    #[test]
+    fn test_remove_map_annotation() {
+        let node = Node::with_span(1, Span::new(0, 1))
+            .add_annotation(Type::I32)
+            .add_annotation(ResolvedImpl(Some(DefId(0))));
+        let mut node = node.map_node(|n| n + 1);
+        *node.get_annotation_mut::<ResolvedImpl>() = ResolvedImpl(Some(DefId(1)));
+
+        let mut copy = node.clone();
+        assert_eq!(copy, node);
+        copy.change_annotation(Type::F32);
+        assert_ne!(copy, node);
+
+        let (node, t) = node.remove_annotation::<Type, _>();
+        assert_eq!(t, Type::I32);
+        assert_eq!(node.node, 2);
+        assert_eq!(node.span(), Span::new(0, 1));
+        assert_eq!(
+            node.get_annotation::<ResolvedImpl>(),
+            &ResolvedImpl(Some(DefId(1)))
+        );
+        assert!(node.get_annotation_unchecked::<Type>().is_none());
+    }
+
+    #[test]
+    fn test_debug_annotations() {
+        let node = Node::with_span(1, Span::new(0, 1))
+            .add_annotation(Scope(None))
+            .add_annotation(Type::I32);
+        assert_eq!(
+            format!("{node:?}"),
+            "Node { node: 1, scope: None, span: Span { start: 0, end: 1 }, type: I32, .. }"
+        );
+    }
 }
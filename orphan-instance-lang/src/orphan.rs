@@ -0,0 +1,170 @@
+//! Orphan impls.
+//! Classifies every trait impl by whether its trait and its struct are defined in the mod of the
+//! impl. In Haskell terms, an impl is local if both are, an orphan if only one of them is, and
+//! fully orphan if neither is. The [`lint`](crate::lint) passes can enforce a policy on orphans,
+//! like requiring them to be named, for a whole program or per mod.
+
+use crate::ast::{Definition, Impl, Span};
+use crate::resolve::{DefId, DefKind, Resolution};
+
+/// How far from its trait and struct a trait impl is defined
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Orphanhood {
+    /// Defined in the mod of both its trait and its struct
+    Local,
+    /// Defined in the mod of only one of its trait and its struct
+    Orphan,
+    /// Defined in the mod of neither its trait nor its struct
+    FullyOrphan,
+}
+
+/// Where the trait and the struct of a trait impl are defined, relative to the impl
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImplOrigin {
+    pub impl_id: DefId,
+    pub trait_id: DefId,
+    pub struct_id: DefId,
+    pub orphanhood: Orphanhood,
+}
+
+impl ImplOrigin {
+    /// Whether the impl has a name a `use` can select it by
+    #[must_use]
+    pub fn is_named(&self, resolution: &Resolution) -> bool {
+        resolution.def(self.impl_id).name.is_some()
+    }
+
+    /// The span of the name of the impl, or of its trait if it is anonymous
+    #[must_use]
+    pub fn span(&self, resolution: &Resolution) -> Span {
+        match &resolution.def(self.impl_id).item.definition {
+            Definition::Impl(Impl::Trait(Some(name), ..)) => name.span(),
+            Definition::Impl(Impl::Trait(None, _, trait_ident, ..)) => trait_ident.span(),
+            _ => resolution.def(self.impl_id).item.span,
+        }
+    }
+
+    /// The classification in words, naming the mods of the trait and the struct if they are not
+    /// the mod of the impl
+    #[must_use]
+    pub fn describe(&self, resolution: &Resolution) -> String {
+        let subject = match resolution.def(self.impl_id).name {
+            Some(_) => format!("impl `{}`", resolution.path(self.impl_id)),
+            None => "anonymous impl".to_string(),
+        };
+        let subject = format!(
+            "{subject} of `{}` for `{}`",
+            resolution.path(self.trait_id),
+            resolution.path(self.struct_id),
+        );
+        let foreign: Vec<_> = [self.trait_id, self.struct_id]
+            .into_iter()
+            .filter(|&id| !is_local(resolution, self.impl_id, id))
+            .map(|id| {
+                format!(
+                    "`{}` is defined {}",
+                    resolution.path(id),
+                    module(resolution, resolution.def(id).parent)
+                )
+            })
+            .collect();
+        match self.orphanhood {
+            Orphanhood::Local => format!("{subject} is local"),
+            Orphanhood::Orphan => format!("{subject} is an orphan, {}", foreign.join(" and ")),
+            Orphanhood::FullyOrphan => {
+                format!("{subject} is fully orphan, {}", foreign.join(" and "))
+            }
+        }
+    }
+}
+
+/// Classifies the trait impl `impl_id`. Returns `None` for inherent impls, and trait impls whose
+/// trait or struct was not resolved
+#[must_use]
+pub fn classify(resolution: &Resolution, impl_id: DefId) -> Option<ImplOrigin> {
+    let (trait_id, struct_id) = resolution.impl_of(impl_id)?;
+    let local = [trait_id, struct_id]
+        .into_iter()
+        .filter(|&id| is_local(resolution, impl_id, id))
+        .count();
+    let orphanhood = match local {
+        2 => Orphanhood::Local,
+        1 => Orphanhood::Orphan,
+        _ => Orphanhood::FullyOrphan,
+    };
+    Some(ImplOrigin {
+        impl_id,
+        trait_id,
+        struct_id,
+        orphanhood,
+    })
+}
+
+/// Classifies every trait impl of the program, in source order
+#[must_use]
+pub fn report(resolution: &Resolution) -> Vec<ImplOrigin> {
+    resolution
+        .defs_of_kind(DefKind::Impl)
+        .filter_map(|id| classify(resolution, id))
+        .collect()
+}
+
+/// Whether `id` is defined in the mod of the impl `impl_id`
+fn is_local(resolution: &Resolution, impl_id: DefId, id: DefId) -> bool {
+    resolution.def(id).parent == resolution.def(impl_id).parent
+}
+
+fn module(resolution: &Resolution, module: Option<DefId>) -> String {
+    match module {
+        Some(id) => format!("in mod `{}`", resolution.path(id)),
+        None => "at the top level".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+    use crate::resolve::resolve;
+    use crate::session::Session;
+
+    #[test]
+    fn test_report() {
+        let input = r"
+pub struct S {}
+pub trait T { fn foo() -> i32 }
+impl A of T for S { fn foo() -> i32 { 1 } }
+mod M {
+    pub struct R {}
+    impl B of T for R { fn foo() -> i32 { 2 } }
+    impl T for S { fn foo() -> i32 { 3 } }
+    pub mod N {
+        pub trait U { fn bar() -> i32 }
+        impl U for M::R { fn bar() -> i32 { 4 } }
+    }
+}
+struct Q {}
+impl C of M::N::U for Q { fn bar() -> i32 { 5 } }
+";
+        let program = parse(input).unwrap();
+        let mut diagnostics = Vec::new();
+        let mut session = Session::new(&mut diagnostics);
+        let resolution = resolve(&mut session, &program);
+
+        let report: Vec<_> = report(&resolution)
+            .iter()
+            .map(|origin| origin.describe(&resolution))
+            .collect();
+        assert_eq!(
+            report,
+            [
+                "impl `A` of `T` for `S` is local",
+                "impl `M::B` of `T` for `M::R` is an orphan, `T` is defined at the top level",
+                "anonymous impl of `T` for `S` is fully orphan, `T` is defined at the top level \
+                 and `S` is defined at the top level",
+                "anonymous impl of `M::N::U` for `M::R` is an orphan, `M::R` is defined in mod `M`",
+                "impl `C` of `M::N::U` for `Q` is an orphan, `M::N::U` is defined in mod `M::N`",
+            ]
+        );
+    }
+}
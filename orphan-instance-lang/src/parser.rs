@@ -1,43 +1,220 @@
-use crate::ast::Program;
+use crate::ast::{Definition, Item, Program, Span};
+use crate::diagnostic::Diagnostic;
+use crate::session::Session;
 use lalrpop_util::lalrpop_mod;
-use regex::Regex;
 
 lalrpop_mod!(
-    #[allow(clippy::ptr_arg)]
+    #[allow(clippy::ptr_arg, clippy::pedantic)]
     #[rustfmt::skip]
     pub oil
 );
 
+type LalrpopError<'input> = lalrpop_util::ParseError<usize, oil::Token<'input>, ActionError>;
+
 #[derive(Debug)]
 pub struct ParseError;
 
-#[allow(clippy::missing_panics_doc)]
+/// An error raised by a grammar action on tokens the lexer accepted
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActionError {
+    pub message: &'static str,
+    pub span: Span,
+}
+
+impl ActionError {
+    /// A number literal that does not fit its type
+    pub(crate) fn out_of_range<T>(span: Span) -> lalrpop_util::ParseError<usize, T, Self> {
+        lalrpop_util::ParseError::User {
+            error: ActionError {
+                message: "literal out of range",
+                span,
+            },
+        }
+    }
+}
+
+/// Attributes read by later passes. Others are kept on the AST, but reported with a warning
+pub const KNOWN_ATTRIBUTES: &[&str] = &["test", "allow", "warn", "deny"];
+
+/// Parses a program, discarding any diagnostics.
+/// Use [`parse_with`] to receive them.
+///
+/// # Errors
+/// Returns [`ParseError`] if the input is not a valid program
 pub fn parse(input: &str) -> Result<Program, ParseError> {
-    let input = remove_comments(input);
-    dbg!(&input);
+    let mut diagnostics = Vec::new();
+    parse_with(&mut Session::new(&mut diagnostics), input)
+}
 
-    let parser = oil::programRuleParser::new();
-    match dbg!(parser.parse(&input)) {
-        Ok(program) => Ok(program),
-        // TODO: Generate proper error message
-        Err(_) => Err(ParseError),
+/// Parses a program, reporting errors and trace output through the session.
+/// Doc comments that do not document an item are ignored like other comments, with a warning
+///
+/// # Errors
+/// Returns [`ParseError`] if the input is not a valid program. The reason is emitted to the session
+pub fn parse_with(session: &mut Session, input: &str) -> Result<Program, ParseError> {
+    session.trace(|| format!("parsing input:\n{input}"));
+
+    match oil::programRuleParser::new().parse(session, input) {
+        Ok(program) => {
+            session.trace(|| format!("parsed program:\n{program:#?}"));
+            check_attributes(session, &program.nodes);
+            Ok(program)
+        }
+        Err(error) => {
+            session.emit(to_diagnostic(&error, input.len()));
+            Err(ParseError)
+        }
     }
 }
 
-fn remove_comments(input: &str) -> std::borrow::Cow<'_, str> {
-    let re = Regex::new(r"//[^\n]*|/\*.*\*/").unwrap();
-    re.replace_all(input, "")
+/// A `///` or `//!` comment, before the grammar knows whether it documents anything
+#[derive(Debug)]
+pub struct DocComment {
+    pub inner: bool,
+    /// Text following the `///` or `//!`, see [`doc_line`]
+    pub text: String,
+    pub span: Span,
+}
+
+/// Warns about a doc comment documenting nothing, which is otherwise ignored like a comment
+pub(crate) fn unused_doc(session: &mut Session, doc: &DocComment) {
+    session.emit(Diagnostic::warning("unused doc comment").with_span(doc.span));
+}
+
+/// The text of the outer doc comments, warning about the inner ones
+fn outer_docs(session: &mut Session, docs: Vec<DocComment>) -> Vec<String> {
+    let mut outer = Vec::new();
+    for doc in docs {
+        if doc.inner {
+            unused_doc(session, &doc);
+        } else {
+            outer.push(doc.text);
+        }
+    }
+    outer
+}
+
+/// Documents `item` with the outer doc comments before it, followed by the inner ones of a mod.
+/// Its span then starts at the first of them
+pub(crate) fn documented_item(
+    session: &mut Session,
+    docs: Vec<DocComment>,
+    mut item: Item,
+) -> Item {
+    if let Some(first) = docs.iter().find(|doc| !doc.inner) {
+        item.span = Span::new(first.span.start, item.span.end);
+    }
+    let mut outer = outer_docs(session, docs);
+    outer.append(&mut item.docs);
+    item.docs = outer;
+    item
+}
+
+/// Documents the methods of a trait or impl with `set_docs`, and warns about the doc comments
+/// after the last one
+pub(crate) fn documented<T>(
+    session: &mut Session,
+    members: Vec<(Vec<DocComment>, T)>,
+    trailing: &[DocComment],
+    set_docs: impl Fn(&mut T, Vec<String>),
+) -> Vec<T> {
+    let members = members
+        .into_iter()
+        .map(|(docs, mut member)| {
+            set_docs(&mut member, outer_docs(session, docs));
+            member
+        })
+        .collect();
+    for doc in trailing {
+        unused_doc(session, doc);
+    }
+    members
+}
+
+/// The inner doc comments starting a program or mod body, which document it, and its items
+pub(crate) fn scope(
+    session: &mut Session,
+    mut items: Vec<(Vec<DocComment>, Item)>,
+    mut trailing: Vec<DocComment>,
+) -> (Vec<String>, Vec<Item>) {
+    let first = items.first_mut().map_or(&mut trailing, |(docs, _)| docs);
+    let count = first.iter().take_while(|doc| doc.inner).count();
+    let docs = first.drain(..count).map(|doc| doc.text).collect();
+    let items = items
+        .into_iter()
+        .map(|(docs, item)| documented_item(session, docs, item))
+        .collect();
+    for doc in &trailing {
+        unused_doc(session, doc);
+    }
+    (docs, items)
+}
+
+/// Warns about attributes not in [`KNOWN_ATTRIBUTES`], including those of nested items
+pub(crate) fn check_attributes(session: &mut Session, items: &[Item]) {
+    for item in items {
+        for attribute in &item.attributes {
+            if !KNOWN_ATTRIBUTES.contains(&attribute.name.as_str()) {
+                session.emit(
+                    Diagnostic::warning(format!("unknown attribute `{}`", attribute.name))
+                        .with_span(attribute.span),
+                );
+            }
+        }
+        if let Definition::Mod(_, children) = &item.definition {
+            check_attributes(session, children);
+        }
+    }
+}
+
+/// Text of a doc comment following its `///` or `//!`, without the separating space
+pub(crate) fn doc_line(text: &str) -> String {
+    text.strip_prefix(' ').unwrap_or(text).to_string()
+}
+
+fn to_diagnostic(error: &LalrpopError, eof: usize) -> Diagnostic {
+    use lalrpop_util::ParseError as E;
+
+    let expected = |expected: &[String]| match expected {
+        [] => String::new(),
+        [one] => format!(", expected {one}"),
+        many => format!(", expected one of {}", many.join(", ")),
+    };
+
+    match error {
+        E::InvalidToken { location } => {
+            Diagnostic::error("invalid token").with_span(Span::new(*location, *location + 1))
+        }
+        E::UnrecognizedEof {
+            location,
+            expected: e,
+        } => Diagnostic::error(format!("unexpected end of file{}", expected(e)))
+            .with_span(Span::new(*location, eof)),
+        E::UnrecognizedToken {
+            token: (l, token, r),
+            expected: e,
+        } => Diagnostic::error(format!("unexpected token `{token}`{}", expected(e)))
+            .with_span(Span::new(*l, *r)),
+        E::ExtraToken {
+            token: (l, token, r),
+        } => Diagnostic::error(format!("extra token `{token}`")).with_span(Span::new(*l, *r)),
+        E::User { error } => Diagnostic::error(error.message).with_span(error.span),
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::ast::{AttributeArg, Expr, Ident, Impl, Literal, Visibility};
+    use crate::diagnostic::DiagnosticSink;
+    use crate::node::Node;
+    use crate::session::ParseOptions;
 
     macro_rules! assert_good {
         ($strs:expr, $parser:expr) => {
             let mut success = true;
             for s in $strs {
-                let res = $parser.parse(s);
+                let res = $parser.parse(&mut Session::new(&mut Vec::new()), s);
                 match res {
                     Ok(_) => {}
                     Err(e) => {
@@ -56,7 +233,7 @@ mod tests {
         ($strs:expr, $parser:expr) => {
             let mut success = true;
             for s in $strs {
-                let res = $parser.parse(s);
+                let res = $parser.parse(&mut Session::new(&mut Vec::new()), s);
                 match res {
                     Ok(_) => {
                         dbg!(s);
@@ -79,6 +256,7 @@ mod tests {
             "fn my_function ( ) { }",
             "fn my_function (a: A) { a }",
             "fn my_function (a: A) -> A { a }",
+            "fn my_function (a: A, b: i32) -> A { a }",
         ];
 
         let bad = [
@@ -87,6 +265,9 @@ mod tests {
             "fn my_function (a: A) { fn }",
             "fn my_function (a A) { }",
             "fn my_function (a) { }",
+            "fn my_function (a: A b: i32) { }",
+            "fn my_function (a: A,) { }",
+            "fn my_function (self) { }",
         ];
 
         assert_good!(good, parser);
@@ -102,6 +283,8 @@ mod tests {
             &format!("impl MyStruct {{ {func} }}"),
             &format!("impl MyImpl of MyTrait for MyStruct {{ {func} }}"),
             &format!("impl MyTrait for MyStruct {{ {func} }}"),
+            "impl MyImpl of MyTrait for MyStruct {}",
+            "impl MyStruct { fn get(self) -> i32 { self.a } fn set(self, a: i32) {} }",
         ];
 
         let bad = [
@@ -109,12 +292,55 @@ mod tests {
             &format!("impl MyStruct MyStruct {{ {func} }}"),
             &format!("impl MyImpl of for MyStruct {{ {func} }}"),
             &format!("impl MyImpl MyTrait for MyStruct {{ {func} }}"),
+            "impl MyStruct {}",
+            "impl MyStruct { fn get(self, self) {} }",
         ];
 
         assert_good!(good, parser);
         assert_bad!(bad, parser);
     }
 
+    #[test]
+    fn test_parse_use_item() {
+        let parser = oil::definitionParser::new();
+
+        let good = ["use C;", "use M::C;", "/// Docs\nuse C;"];
+
+        let bad = ["use C", "use C in 1;", "use;", "use C::;"];
+
+        assert_good!(good, parser);
+        assert_bad!(bad, parser);
+    }
+
+    #[test]
+    fn test_parse_visibility() {
+        let parser = oil::definitionParser::new();
+
+        let good = [
+            "pub struct S {}",
+            "pub mod M { pub fn f() {} }",
+            "/// Docs\n#[test]\npub impl C of T for S { fn f() {} }",
+        ];
+
+        let bad = [
+            "pub pub fn f() {}",
+            "pub #[test] fn f() {}",
+            "fn pub f() {}",
+        ];
+
+        assert_good!(good, parser);
+        assert_bad!(bad, parser);
+
+        let item = parser
+            .parse(&mut Session::new(&mut Vec::new()), "pub fn f() {}")
+            .unwrap();
+        assert_eq!(item.visibility, Visibility::Public);
+        let item = parser
+            .parse(&mut Session::new(&mut Vec::new()), "fn f() {}")
+            .unwrap();
+        assert_eq!(item.visibility, Visibility::Private);
+    }
+
     #[test]
     fn test_parse_trait() {
         let parser = oil::traitParser::new();
@@ -125,12 +351,77 @@ mod tests {
             "trait MyTrait { fn my_func() -> A }",
             "trait MyTrait { fn my_func(a: A) -> A }",
             "trait MyTrait { fn my_func(a: A) -> () }",
+            "trait MyTrait { fn my_func(a: A) -> i32 { 1 } fn other() -> () }",
+            "trait MyTrait: Super {}",
+            "trait MyTrait: M::Super + Other { fn my_func() -> A }",
+            "trait MyTrait { fn my_func(self) -> A fn other(self, a: A) -> i32 { self.my_func() } }",
+        ];
+
+        let bad = [
+            "trait trait",
+            "trait MyTrait",
+            "trait MyTrait: {}",
+            "trait MyTrait: A + {}",
+            "trait MyTrait { fn my_func() { 1 } }",
+            "trait MyTrait { fn my_func(a: A, self) -> A }",
+        ];
+
+        assert_good!(good, parser);
+        assert_bad!(bad, parser);
+    }
+
+    #[test]
+    fn test_parse_type() {
+        let parser = oil::explicit_typeParser::new();
+
+        let good = [
+            "i32",
+            "()",
+            "M::S",
+            "Set[Hash = C]",
+            "M::Set[M::Hash = M::C, Eq = D]",
+        ];
+
+        let bad = ["Set[]", "Set[Hash]", "Set[Hash = C,]", "i32[Hash = C]"];
+
+        assert_good!(good, parser);
+        assert_bad!(bad, parser);
+
+        let t = parser
+            .parse(&mut Session::new(&mut Vec::new()), "Set[Hash = C, Eq = D]")
+            .unwrap();
+        assert_eq!(t.to_string(), "Set[Hash = C, Eq = D]");
+    }
+
+    #[test]
+    fn test_parse_generics() {
+        let parser = oil::definitionParser::new();
+
+        let good = [
+            "struct List<T> { head: T }",
+            "struct Pair<A, B: Show + M::Eq> { a: A, b: B }",
+            "fn max<T: Ord>(a: T, b: T) -> T { a }",
+            "fn wrap<T>(a: T) -> List<T> { List { head: a } }",
+            "impl<T: Show> Show for List<T> {}",
+            "impl<T> C of Show for Map<i32, List<T>> {}",
+            "impl Show for List<i32> {}",
         ];
 
-        let bad = ["trait trait", "trait MyTrait"];
+        let bad = [
+            "struct List<> {}",
+            "struct List<T: > {}",
+            "fn max<T>[a: T] {}",
+            "impl<T> List<T> { fn f() {} }",
+            "impl Show for T<> {}",
+        ];
 
         assert_good!(good, parser);
         assert_bad!(bad, parser);
+
+        let t = oil::explicit_typeParser::new()
+            .parse(&mut Session::new(&mut Vec::new()), "Map<i32, List<T>>")
+            .unwrap();
+        assert_eq!(t.to_string(), "Map<i32, List<T>>");
     }
 
     #[test]
@@ -193,6 +484,124 @@ mod tests {
         assert_bad!(bad, parser);
     }
 
+    /// Renders an expression as a fully parenthesised s-expression, exposing the tree shape
+    fn sexp(expr: &Node<Expr>) -> String {
+        match &expr.node {
+            Expr::Literal(Literal::I32(i)) => i.to_string(),
+            Expr::Literal(Literal::F32(f)) => f.to_string(),
+            Expr::Ident(Ident::Simple(s, _)) => s.clone(),
+            Expr::Paren(e) => sexp(e),
+            Expr::Unary(op, e) => format!("({op:?} {})", sexp(e)),
+            Expr::Binary(l, op, r) => format!("({op:?} {} {})", sexp(l), sexp(r)),
+            e => format!("{e:?}"),
+        }
+    }
+
+    #[test]
+    fn test_parse_operators() {
+        let parser = oil::exprParser::new();
+
+        let good = [
+            "1 != 2",
+            "1 % 2",
+            "1 && 2",
+            "1 || 2",
+            "-1",
+            "!1",
+            "--1",
+            "!-a",
+            "a - -1",
+            "-(1 + 2)",
+            "!(a == b) || c != d && e",
+        ];
+
+        let bad = ["1 !", "1 -", "1 && ", "|| 1", "1 ! 2", "1 & 2", "1 | 2"];
+
+        assert_good!(good, parser);
+        assert_bad!(bad, parser);
+    }
+
+    #[test]
+    fn test_operator_precedence() {
+        let parser = oil::exprParser::new();
+
+        let cases = [
+            ("1 == 2", "(Equal 1 2)"),
+            ("1 + 2 * 3", "(Plus 1 (Mult 2 3))"),
+            ("1 * 2 + 3", "(Plus (Mult 1 2) 3)"),
+            ("1 + 2 % 3", "(Plus 1 (Modulo 2 3))"),
+            ("1 + 2 < 3 * 4", "(LT (Plus 1 2) (Mult 3 4))"),
+            ("a == b && c != d", "(And (Equal a b) (NotEqual c d))"),
+            ("a && b || c && d", "(Or (And a b) (And c d))"),
+            ("a || b && c", "(Or a (And b c))"),
+            ("-1 + 2", "(Plus (Neg 1) 2)"),
+            ("-a * b", "(Mult (Neg a) b)"),
+            ("!a && b", "(And (Not a) b)"),
+            ("!a == b", "(Equal (Not a) b)"),
+            ("-(1 + 2)", "(Neg (Plus 1 2))"),
+            ("!-a", "(Not (Neg a))"),
+            ("a - -1", "(Minus a (Neg 1))"),
+        ];
+
+        for (input, expected) in cases {
+            assert_eq!(
+                sexp(
+                    &parser
+                        .parse(&mut Session::new(&mut Vec::new()), input)
+                        .unwrap()
+                ),
+                expected,
+                "{input}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_operator_associativity() {
+        let parser = oil::exprParser::new();
+
+        let cases = [
+            ("1 - 2 - 3", "(Minus (Minus 1 2) 3)"),
+            ("1 / 2 / 3", "(Div (Div 1 2) 3)"),
+            ("1 % 2 * 3", "(Mult (Modulo 1 2) 3)"),
+            ("a < b == c", "(Equal (LT a b) c)"),
+            ("a != b != c", "(NotEqual (NotEqual a b) c)"),
+            ("a && b && c", "(And (And a b) c)"),
+            ("a || b || c", "(Or (Or a b) c)"),
+        ];
+
+        for (input, expected) in cases {
+            assert_eq!(
+                sexp(
+                    &parser
+                        .parse(&mut Session::new(&mut Vec::new()), input)
+                        .unwrap()
+                ),
+                expected,
+                "{input}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_expr_spans() {
+        let input = "{ let x = (1 + 2) * y; f(x) }";
+        let expr = oil::exprParser::new()
+            .parse(&mut Session::new(&mut Vec::new()), input)
+            .unwrap();
+        assert_eq!(expr.span(), Span::new(0, input.len()));
+
+        let Expr::Block(statements, Some(tail)) = &expr.node else {
+            panic!("expected a block with a tail, got {expr:?}");
+        };
+        assert_eq!(statements[0].span(), Span::new(2, 21));
+        let Expr::Let(_, _, value) = &statements[0].node else {
+            panic!("expected a let, got {:?}", statements[0]);
+        };
+        assert_eq!(value.span(), Span::new(10, 21));
+        assert_eq!(tail.span(), Span::new(23, 27));
+    }
+
     #[test]
     fn test_parse_expr_statement() {
         let parser = oil::expr_statementParser::new();
@@ -215,24 +624,273 @@ mod tests {
     }
 
     #[test]
-    fn test_remove_comments() {
+    fn test_comments_are_skipped() {
         let input = r"
 // Comment here
 // In multiple lines
 struct A { }
 
-// And now
-let x = /* an inline */ 3; // comment
+/* A block
+   comment */
+fn main() { let x = /* an inline */ 3; x } // comment
         ";
-        let output = r"
 
+        assert!(parse(input).is_ok());
+    }
+
+    #[test]
+    fn test_doc_comments() {
+        let input = r"
+//! The program
+//! in two lines
 
+/// A struct
 struct A { }
 
+//// Not a doc comment
+/// A trait
+trait B {
+    /// A trait method
+    fn foo() -> i32
+}
 
-let x =  3; 
-        ";
+/// An impl
+impl C of B for A {
+    /// An impl method
+    fn foo() -> i32 { 1 }
+}
+
+/// A module
+mod D {
+    //! Inner module docs
+    //!
+    ///No space
+    fn bar() {}
+}
+";
+
+        let program = parse(input).unwrap();
+        assert_eq!(program.docs, ["The program", "in two lines"]);
+
+        let docs: Vec<_> = program.nodes.iter().map(|item| &item.docs[..]).collect();
+        assert_eq!(docs[0], ["A struct"]);
+        assert_eq!(docs[1], ["A trait"]);
+        assert_eq!(docs[2], ["An impl"]);
+        assert_eq!(docs[3], ["A module", "Inner module docs", ""]);
+
+        let Definition::Trait(_, _, methods) = &program.nodes[1].definition else {
+            panic!("Expected trait");
+        };
+        assert_eq!(methods[0].docs, ["A trait method"]);
+
+        let Definition::Impl(Impl::Trait(.., functions)) = &program.nodes[2].definition else {
+            panic!("Expected trait impl");
+        };
+        assert_eq!(functions[0].docs, ["An impl method"]);
+
+        let Definition::Mod(_, items) = &program.nodes[3].definition else {
+            panic!("Expected mod");
+        };
+        assert_eq!(items[0].docs, ["No space"]);
+        assert!(matches!(items[0].definition, Definition::Func(_)));
+    }
+
+    #[test]
+    fn test_misplaced_doc_comments() {
+        let inputs = [
+            "fn main() { /// doc\n 1 }",
+            "fn main() { let a = 1; /// doc\n /// more\n a }",
+            "struct A { /// doc\n a: i32 }",
+            "struct A { a: i32, /// doc\n b: i32 }",
+            "/// doc",
+            "struct A {} //! doc",
+            "mod M { fn f() {} /// doc\n }",
+            "trait T { fn f() -> i32 /* docs */ /// doc\n }",
+            "fn main() { 1 + /// doc\n 2 }",
+            "fn main() { f(/// doc\n 1, 2) }",
+            "fn main() { /// doc\n let a = 1; /// doc\n use C in a }",
+            "fn main() { 1; /// doc\n }",
+            "//! doc\n/// doc\n//! doc\nfn main() {}",
+        ];
+
+        for input in inputs {
+            let mut diagnostics = Vec::new();
+            let result = parse_with(&mut Session::new(&mut diagnostics), input);
+            assert!(result.is_ok(), "{input}: {diagnostics:?}");
+            assert!(!diagnostics.is_empty(), "{input}");
+            for diagnostic in &diagnostics {
+                assert!(!diagnostic.is_error());
+                assert_eq!(diagnostic.message, "unused doc comment");
+            }
+        }
+
+        let input = "struct A {\n    /// doc\n    a: i32,\n}";
+        let mut diagnostics = Vec::new();
+        let program = parse_with(&mut Session::new(&mut diagnostics), input).unwrap();
+        let start = input.find("///").unwrap();
+        assert_eq!(diagnostics[0].span, Some(Span::new(start, start + 7)));
+        assert!(program.nodes[0].docs.is_empty());
+
+        // Reported in source order, once each
+        let input = "fn main() {\n    /// one\n    let a = 1;\n    a /// two\n}";
+        let mut diagnostics = Vec::new();
+        parse_with(&mut Session::new(&mut diagnostics), input).unwrap();
+        let spans: Vec<_> = diagnostics.iter().map(|d| d.span.unwrap()).collect();
+        let one = input.find("/// one").unwrap();
+        let two = input.find("/// two").unwrap();
+        assert_eq!(spans, [Span::new(one, one + 7), Span::new(two, two + 7)]);
+
+        // Not a doc comment of the program, but still a parse error
+        assert!(parse("/// doc\nfn main() { 1 2 }").is_err());
+        assert!(parse("fn main() { \"/// doc\" }").is_err());
+    }
+
+    #[test]
+    fn test_parse_attribute() {
+        let parser = oil::attributeParser::new();
+
+        let good = [
+            "#[test]",
+            "#[default]",
+            "#[deprecated()]",
+            "#[deprecated(\"use bar\")]",
+            "#[allow(a, b, \"c\")]",
+            "# [ test ]",
+        ];
+
+        let bad = [
+            "#test",
+            "#[]",
+            "#[test",
+            "#[a::b]",
+            "#[test(1)]",
+            "#[test(a b)]",
+            "#[test(\"a)]",
+        ];
+
+        assert_good!(good, parser);
+        assert_bad!(bad, parser);
+    }
+
+    #[test]
+    fn test_attributes() {
+        let input = r#"
+/// Docs come first
+#[default]
+impl C of B for A {
+    fn foo() -> i32 { 1 }
+}
+
+#[test]
+#[deprecated("use other")]
+fn foo() {}
+
+#[deprecated]
+mod M {
+    #[test]
+    struct S {}
+}
+"#;
+
+        let mut diagnostics = Vec::new();
+        let program = parse_with(&mut Session::new(&mut diagnostics), input).unwrap();
+        // No pass reads them, but they are kept on the AST
+        let messages: Vec<_> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                "unknown attribute `default`",
+                "unknown attribute `deprecated`",
+                "unknown attribute `deprecated`"
+            ]
+        );
+
+        assert!(program.nodes[0].has_attribute("default"));
+        assert_eq!(program.nodes[0].docs, ["Docs come first"]);
+        assert!(program.nodes[1].has_attribute("test"));
+        assert!(!program.nodes[1].has_attribute("default"));
+
+        let deprecated = program.nodes[1].attribute("deprecated").unwrap();
+        assert!(matches!(&deprecated.args[..], [AttributeArg::Str(s)] if s == "use other"));
+
+        let Definition::Mod(_, items) = &program.nodes[2].definition else {
+            panic!("Expected mod");
+        };
+        assert!(program.nodes[2].has_attribute("deprecated"));
+        assert!(items[0].has_attribute("test"));
+    }
+
+    #[test]
+    fn test_unknown_attribute_warns() {
+        let input = "mod M {\n    #[inline]\n    fn foo() {}\n}";
+
+        let mut diagnostics = Vec::new();
+        let program = parse_with(&mut Session::new(&mut diagnostics), input).unwrap();
+
+        assert_eq!(diagnostics.len(), 1);
+        assert!(!diagnostics[0].is_error());
+        assert_eq!(diagnostics[0].message, "unknown attribute `inline`");
+        assert_eq!(diagnostics[0].span, Some(Span::new(12, 21)));
+
+        let Definition::Mod(_, items) = &program.nodes[0].definition else {
+            panic!("Expected mod");
+        };
+        assert!(items[0].has_attribute("inline"));
+    }
+
+    #[test]
+    fn test_error_is_reported_to_session() {
+        // The comment must not shift the reported location
+        let input = "// fn\nfn main() { 1 2 }";
+
+        let mut diagnostics = Vec::new();
+        let result = parse_with(&mut Session::new(&mut diagnostics), input);
+
+        assert!(result.is_err());
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].is_error());
+        assert!(diagnostics[0].message.starts_with("unexpected token `2`"));
+        assert_eq!(diagnostics[0].span, Some(Span::new(20, 21)));
+    }
+
+    #[test]
+    fn test_literal_out_of_range() {
+        let input = "fn main() -> i32 { 2147483647 + 2147483648 }";
+
+        let mut diagnostics = Vec::new();
+        let result = parse_with(&mut Session::new(&mut diagnostics), input);
+
+        assert!(result.is_err());
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].message, "literal out of range");
+        assert_eq!(diagnostics[0].span, Some(Span::new(32, 42)));
+
+        let float = format!("fn main() -> f32 {{ {}.0 }}", "9".repeat(40));
+        assert!(parse(&float).is_err());
+        assert!(parse("fn main() -> f32 { 3.5 }").is_ok());
+    }
+
+    #[test]
+    fn test_trace_is_opt_in() {
+        #[derive(Default)]
+        struct Traces(Vec<String>);
+        impl DiagnosticSink for Traces {
+            fn emit(&mut self, _: Diagnostic) {}
+            fn trace(&mut self, message: &str) {
+                self.0.push(message.into());
+            }
+        }
+
+        let input = "fn main() {}";
+
+        let mut quiet = Traces::default();
+        parse_with(&mut Session::new(&mut quiet), input).unwrap();
+        assert!(quiet.0.is_empty());
 
-        assert_eq!(remove_comments(input), output);
+        let mut traced = Traces::default();
+        let options = ParseOptions { trace: true };
+        parse_with(&mut Session::with_options(options, &mut traced), input).unwrap();
+        assert_eq!(traced.0.len(), 2);
+        assert!(traced.0[0].contains(input));
     }
 }
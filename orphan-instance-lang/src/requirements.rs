@@ -0,0 +1,523 @@
+//! Implicit impl requirements.
+//! A function whose trait method call cannot pick an impl is not an error on its own. The impl it
+//! leaves open becomes a requirement of the function, part of its signature, and every call of the
+//! function has to discharge it. A call discharges a requirement the way
+//! [`instance`](crate::instance) resolution picks the impl of a method call: through a `use`
+//! around the call, or a unique impl in the caller's mod. A caller that cannot discharge a
+//! requirement inherits it, and is warned about it.
+//!
+//! Entry points, `main` and `#[test]` functions, have no caller to inherit their requirements, and
+//! neither do impl methods and default methods of traits. Requirements they leave open are errors,
+//! reported for default methods in every impl inheriting them. So are supertraits a trait impl
+//! cannot pick the impl to build on for.
+
+use crate::ast::{Definition, Impl, Span};
+use crate::coherence::ImplTable;
+use crate::diagnostic::{Diagnostic, Severity};
+use crate::incremental::TextEdit;
+use crate::instance::{
+    needed_uses, pick, AmbiguousCall, AmbiguousSupertrait, FunctionCall, Instances,
+};
+use crate::resolve::{DefId, Resolution};
+use crate::session::Session;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt::Write;
+
+/// An impl of a trait for a struct, left for the caller to pick
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Requirement {
+    pub trait_id: DefId,
+    pub struct_id: DefId,
+}
+
+#[derive(Debug, Default)]
+pub struct Requirements {
+    functions: HashMap<DefId, BTreeSet<Requirement>>,
+    /// The impl picked for each requirement of a call, keyed by the span of the invocation
+    pub discharged: HashMap<Span, Vec<(Requirement, DefId)>>,
+    /// Spans of the impl names of the `use` expressions some call needs to discharge a
+    /// requirement, see [`needed_uses`]
+    pub needed_uses: HashSet<Span>,
+}
+
+impl Requirements {
+    /// The requirements a function leaves to its callers
+    pub fn of(&self, function: DefId) -> impl Iterator<Item = Requirement> + '_ {
+        self.functions.get(&function).into_iter().flatten().copied()
+    }
+
+    fn insert(&mut self, function: DefId, requirement: Requirement) -> bool {
+        self.functions
+            .entry(function)
+            .or_default()
+            .insert(requirement)
+    }
+}
+
+/// Infers the requirements of every function, and checks every call discharges or inherits the
+/// requirements of the function it calls
+pub fn check(
+    session: &mut Session,
+    resolution: &Resolution,
+    table: &ImplTable,
+    instances: &Instances,
+) -> Requirements {
+    let mut requirements = Requirements::default();
+    // The function inheriting what a call leaves open, if any
+    let heir = |caller: Option<DefId>| caller.filter(|&id| !is_entry(resolution, id));
+
+    for ambiguous in &instances.ambiguous_supertraits {
+        session.emit(supertrait_ambiguity(resolution, ambiguous));
+    }
+
+    for call in &instances.ambiguous {
+        let Some(requirement) = requirement(resolution, call) else {
+            continue;
+        };
+        if let Some(caller) = heir(call.caller) {
+            requirements.insert(caller, requirement);
+        } else {
+            let subject = format!("call of `{}`", call.method);
+            let message = message(resolution, &subject, requirement, &call.candidates);
+            let diagnostic = ambiguity(resolution, message, call.span, &call.candidates);
+            session.emit(inherited_by(resolution, diagnostic, call.inheritor));
+        }
+    }
+
+    // Requirements flow from callees to callers, possibly through recursion
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for call in &instances.calls {
+            let Some(caller) = heir(call.caller) else {
+                continue;
+            };
+            let open: Vec<_> = requirements
+                .of(call.callee)
+                .filter(|&r| discharge(resolution, table, call, r).is_err())
+                .collect();
+            for requirement in open {
+                changed |= requirements.insert(caller, requirement);
+            }
+        }
+    }
+
+    for call in &instances.calls {
+        let callee: Vec<_> = requirements.of(call.callee).collect();
+        for requirement in callee {
+            let candidates = table.impls(requirement.trait_id, requirement.struct_id);
+            let needed = needed_uses(resolution, &call.context, &[], candidates);
+            requirements.needed_uses.extend(needed);
+
+            let candidates = match discharge(resolution, table, call, requirement) {
+                Ok(id) => {
+                    let discharged = requirements.discharged.entry(call.span).or_default();
+                    discharged.push((requirement, id));
+                    continue;
+                }
+                Err(candidates) => candidates,
+            };
+
+            let subject = format!("call of `{}`", resolution.path(call.callee));
+            let mut message = message(resolution, &subject, requirement, &candidates);
+            let heir = heir(call.caller);
+            if let Some(heir) = heir {
+                let heir = resolution.path(heir);
+                write!(message, ". `{heir}` now requires it from its callers").unwrap();
+            }
+            let diagnostic = ambiguity(resolution, message, call.span, &candidates);
+            let mut diagnostic = inherited_by(resolution, diagnostic, call.inheritor);
+            if heir.is_some() {
+                diagnostic.severity = Severity::Warning;
+            }
+            session.emit(diagnostic);
+        }
+    }
+
+    requirements
+}
+
+/// Entry points cannot pass requirements on to a caller
+pub(crate) fn is_entry(resolution: &Resolution, function: DefId) -> bool {
+    let def = resolution.def(function);
+    def.name == Some("main") || def.item.has_attribute("test")
+}
+
+/// Picks the impl discharging `requirement` at `call`
+fn discharge(
+    resolution: &Resolution,
+    table: &ImplTable,
+    call: &FunctionCall,
+    requirement: Requirement,
+) -> Result<DefId, Vec<DefId>> {
+    let candidates = table.impls(requirement.trait_id, requirement.struct_id);
+    pick(resolution, &call.context, &[], candidates)
+}
+
+/// The requirement an ambiguous method call leaves open, taken from the trait of its candidates
+fn requirement(resolution: &Resolution, call: &AmbiguousCall) -> Option<Requirement> {
+    let (trait_id, _) = call
+        .candidates
+        .iter()
+        .find_map(|&id| resolution.impl_of(id))?;
+    Some(Requirement {
+        trait_id,
+        struct_id: call.receiver,
+    })
+}
+
+fn message(
+    resolution: &Resolution,
+    subject: &str,
+    requirement: Requirement,
+    candidates: &[DefId],
+) -> String {
+    let problem = match candidates.len() {
+        0 => "none is visible here".to_string(),
+        n => format!("{n} conflicting implementations exist"),
+    };
+    format!(
+        "{subject} requires an implementation of `{}` for `{}`, but {problem}",
+        resolution.path(requirement.trait_id),
+        resolution.path(requirement.struct_id),
+    )
+}
+
+/// Labels every candidate of an ambiguous call, and suggests wrapping the call in a block that
+/// `use`s each candidate that has a name, which is valid wherever the call is
+fn ambiguity(
+    resolution: &Resolution,
+    message: String,
+    span: Span,
+    candidates: &[DefId],
+) -> Diagnostic {
+    let diagnostic = Diagnostic::error(message).with_span(span);
+    let mut diagnostic = label_candidates(resolution, diagnostic, candidates);
+
+    for &id in candidates {
+        if resolution.def(id).name.is_none() {
+            continue;
+        }
+        let path = resolution.path(id);
+        let edits = vec![
+            TextEdit {
+                span: Span::new(span.start, span.start),
+                text: format!("{{ use {path} in "),
+            },
+            TextEdit {
+                span: Span::new(span.end, span.end),
+                text: " }".into(),
+            },
+        ];
+        diagnostic = diagnostic.with_suggestion(format!("use `{path}`"), edits);
+    }
+    diagnostic
+}
+
+/// Points at the trait impl inheriting the default method an ambiguous call is in, if any
+fn inherited_by(
+    resolution: &Resolution,
+    diagnostic: Diagnostic,
+    inheritor: Option<DefId>,
+) -> Diagnostic {
+    match inheritor {
+        Some(id) => diagnostic.with_label(
+            resolution.def(id).item.span,
+            "in a default method inherited by this impl",
+        ),
+        None => diagnostic,
+    }
+}
+
+/// Labels every candidate of an ambiguity with where it is defined
+fn label_candidates(
+    resolution: &Resolution,
+    mut diagnostic: Diagnostic,
+    candidates: &[DefId],
+) -> Diagnostic {
+    for &id in candidates {
+        let def = resolution.def(id);
+        let module = match def.parent {
+            Some(parent) => format!("in mod `{}`", resolution.path(parent)),
+            None => "at the top level".to_string(),
+        };
+        let label = match def.name {
+            Some(_) => format!("candidate `{}` defined {module}", resolution.path(id)),
+            None => format!("anonymous candidate defined {module}"),
+        };
+        diagnostic = diagnostic.with_label(def.item.span, label);
+    }
+    diagnostic
+}
+
+/// Reports a trait impl that cannot pick the impl of a supertrait it builds on
+fn supertrait_ambiguity(resolution: &Resolution, ambiguous: &AmbiguousSupertrait) -> Diagnostic {
+    let def = resolution.def(ambiguous.impl_id);
+    let Definition::Impl(Impl::Trait(_, _, trait_ident, ..)) = &def.item.definition else {
+        unreachable!("only trait impls build on supertraits");
+    };
+    let subject = match def.name {
+        Some(_) => format!("impl `{}`", resolution.path(ambiguous.impl_id)),
+        None => format!(
+            "impl of `{trait_ident}` for `{}`",
+            resolution.path(ambiguous.struct_id)
+        ),
+    };
+    let requirement = Requirement {
+        trait_id: ambiguous.supertrait,
+        struct_id: ambiguous.struct_id,
+    };
+    let message = message(resolution, &subject, requirement, &ambiguous.candidates);
+    let diagnostic = Diagnostic::error(message).with_span(trait_ident.span());
+    label_candidates(resolution, diagnostic, &ambiguous.candidates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+    use crate::{coherence, instance, typeck};
+
+    const IMPLS: &str = r"
+struct S {}
+trait T { fn foo(self) -> i32 }
+mod M {
+    impl A of T for S { fn foo(self) -> i32 { 1 } }
+}
+impl B of T for S { fn foo(self) -> i32 { 2 } }
+impl C of T for S { fn foo(self) -> i32 { 3 } }
+";
+
+    /// Runs every pass up to requirement checking, returning the requirements of each function in
+    /// `functions` as `trait for struct` paths along with the diagnostics
+    fn check_requirements(input: &str, functions: &[&str]) -> (Vec<Vec<String>>, Vec<Diagnostic>) {
+        let program = parse(&format!("{IMPLS}{input}")).unwrap();
+        let mut diagnostics = Vec::new();
+        let mut session = Session::new(&mut diagnostics);
+        let resolution = crate::resolve::resolve(&mut session, &program);
+        let table = coherence::check(&mut session, &resolution);
+        let results = typeck::check(&mut session, &resolution, &program);
+        let instances = instance::resolve(&resolution, &table, &results, &program);
+        let requirements = check(&mut session, &resolution, &table, &instances);
+
+        let functions = functions
+            .iter()
+            .map(|&name| {
+                let id = resolution
+                    .defs_of_kind(crate::resolve::DefKind::Func)
+                    .find(|&id| resolution.path(id) == name)
+                    .unwrap();
+                requirements
+                    .of(id)
+                    .map(|r| {
+                        let t = resolution.path(r.trait_id);
+                        format!("{t} for {}", resolution.path(r.struct_id))
+                    })
+                    .collect()
+            })
+            .collect();
+        (functions, diagnostics)
+    }
+
+    #[test]
+    fn test_requirements_propagate_to_callers() {
+        let input = r"
+fn f(s: S) -> i32 { s.foo() }
+fn g(s: S) -> i32 { f(s) }
+fn h(s: S) -> i32 { use B in f(s) }
+mod N {
+    impl D of T for S { fn foo(self) -> i32 { 4 } }
+    fn local(s: S) -> i32 { g(s) }
+}
+";
+        let (functions, diagnostics) = check_requirements(input, &["f", "g", "h", "N::local"]);
+        assert_eq!(
+            functions,
+            [
+                vec!["T for S".to_string()],
+                vec!["T for S".into()],
+                vec![],
+                vec![]
+            ]
+        );
+
+        let [diagnostic] = diagnostics.as_slice() else {
+            panic!("Expected a single diagnostic, got {diagnostics:?}");
+        };
+        assert_eq!(diagnostic.severity, Severity::Warning);
+        assert_eq!(
+            diagnostic.message,
+            "call of `f` requires an implementation of `T` for `S`, \
+             but 2 conflicting implementations exist. `g` now requires it from its callers"
+        );
+    }
+
+    #[test]
+    fn test_recursive_requirements() {
+        let input = r"
+fn f(s: S) -> i32 { g(s) + s.foo() }
+fn g(s: S) -> i32 { f(s) }
+";
+        let (functions, diagnostics) = check_requirements(input, &["f", "g"]);
+        assert_eq!(functions, [vec!["T for S"], vec!["T for S"]]);
+        assert_eq!(diagnostics.len(), 2);
+    }
+
+    #[test]
+    fn test_entry_points_cannot_pass_requirements_on() {
+        let input = r"
+fn f(s: S) -> i32 { s.foo() }
+fn main() { f(S {}); }
+#[test]
+fn test_f() { use C in f(S {}); }
+";
+        let (functions, diagnostics) = check_requirements(input, &["main", "test_f"]);
+        assert_eq!(functions, [Vec::<String>::new(), vec![]]);
+        let [diagnostic] = diagnostics.as_slice() else {
+            panic!("Expected a single diagnostic, got {diagnostics:?}");
+        };
+        assert_eq!(diagnostic.severity, Severity::Error);
+    }
+
+    #[test]
+    fn test_ambiguous_supertraits() {
+        let input = r"
+trait U: T { fn bar() -> i32 }
+impl U for S { fn bar() -> i32 { 1 } }
+impl D of U for S { fn bar() -> i32 { use B in { 2 } } }
+mod N {
+    use C;
+    impl E of U for S { fn bar() -> i32 { 3 } }
+}
+";
+        let (_, diagnostics) = check_requirements(input, &[]);
+        let messages: Vec<_> = diagnostics.iter().map(|d| &d.message).collect();
+        assert_eq!(
+            messages,
+            [
+                "impl of `U` for `S` requires an implementation of `T` for `S`, \
+                 but 2 conflicting implementations exist",
+                "impl `D` requires an implementation of `T` for `S`, \
+                 but 2 conflicting implementations exist",
+            ]
+        );
+        assert_eq!(diagnostics[0].labels.len(), 2);
+    }
+
+    #[test]
+    fn test_ambiguity_diagnostic() {
+        let input = format!("{IMPLS}fn main() -> i32 {{ let s = S {{}}; s.foo() }}\n");
+        let program = parse(&input).unwrap();
+        let mut diagnostics = Vec::new();
+        let checked = crate::driver::check(&mut Session::new(&mut diagnostics), program);
+        assert!(checked.is_none());
+
+        let [diagnostic] = diagnostics.as_slice() else {
+            panic!("Expected a single diagnostic, got {diagnostics:?}");
+        };
+        assert_eq!(
+            diagnostic.message,
+            "call of `foo` requires an implementation of `T` for `S`, \
+             but 2 conflicting implementations exist"
+        );
+        let call = input.find("s.foo()").unwrap();
+        assert_eq!(diagnostic.span, Some(Span::new(call, call + 7)));
+
+        let labels: Vec<_> = diagnostic.labels.iter().map(|l| &l.message).collect();
+        assert_eq!(
+            labels,
+            [
+                "candidate `B` defined at the top level",
+                "candidate `C` defined at the top level"
+            ]
+        );
+        assert_eq!(
+            diagnostic.labels[0].span.start,
+            input.find("impl B").unwrap()
+        );
+
+        let fixed: Vec<_> = diagnostic
+            .suggestions
+            .iter()
+            .map(|s| s.apply(&input))
+            .collect();
+        assert!(fixed[0].contains("let s = S {}; { use B in s.foo() } }"));
+        assert!(fixed[1].contains("let s = S {}; { use C in s.foo() } }"));
+        for fixed in fixed {
+            let program = parse(&fixed).unwrap();
+            let mut diagnostics = Vec::new();
+            let checked = crate::driver::check(&mut Session::new(&mut diagnostics), program);
+            assert!(diagnostics.is_empty(), "{diagnostics:?}");
+            assert!(checked.is_some());
+        }
+    }
+
+    #[test]
+    fn test_ambiguity_suggestion_outside_tail() {
+        for body in [
+            "let x = s.foo(); x",
+            "1 + s.foo()",
+            "let x = get(s.foo()); x",
+        ] {
+            let input =
+                format!("{IMPLS}fn get(x: i32) -> i32 {{ x }}\nfn main() -> i32 {{ let s = S {{}}; {body} }}\n");
+            let program = parse(&input).unwrap();
+            let mut diagnostics = Vec::new();
+            crate::driver::check(&mut Session::new(&mut diagnostics), program);
+            let [diagnostic] = diagnostics.as_slice() else {
+                panic!("Expected a single diagnostic, got {diagnostics:?}");
+            };
+            assert_eq!(diagnostic.suggestions.len(), 2);
+
+            for suggestion in &diagnostic.suggestions {
+                let fixed = suggestion.apply(&input);
+                let program = parse(&fixed).unwrap_or_else(|e| panic!("{fixed}: {e:?}"));
+                let mut diagnostics = Vec::new();
+                let checked = crate::driver::check(&mut Session::new(&mut diagnostics), program);
+                assert!(diagnostics.is_empty(), "{fixed}: {diagnostics:?}");
+                assert!(checked.is_some());
+            }
+        }
+    }
+
+    #[test]
+    fn test_inherited_default_method_ambiguity() {
+        let input = r"
+struct S {}
+trait Eq { fn eq(self) -> i32 }
+trait Ord: Eq { fn cmp(s: S) -> i32 { s.eq() } }
+impl A of Eq for S { fn eq(self) -> i32 { 1 } }
+impl B of Eq for S { fn eq(self) -> i32 { 2 } }
+mod M {
+    use B;
+    pub impl C of Ord for S {}
+}
+fn main() -> i32 { M::C::cmp(S {}) }
+";
+        let program = parse(input).unwrap();
+        let mut diagnostics = Vec::new();
+        let checked = crate::driver::check(&mut Session::new(&mut diagnostics), program);
+        assert!(diagnostics.is_empty(), "{diagnostics:?}");
+        assert!(checked.is_some());
+
+        let input = input.replace("pub impl C of Ord for S {}", "");
+        let input = input.replace("M::C::cmp", "D::cmp");
+        let input = format!("{input}impl D of Ord for S {{}}\n");
+        let program = parse(&input).unwrap();
+        let mut diagnostics = Vec::new();
+        crate::driver::check(&mut Session::new(&mut diagnostics), program);
+        let messages: Vec<_> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                "impl `D` requires an implementation of `Eq` for `S`, \
+                 but 2 conflicting implementations exist",
+                "call of `eq` requires an implementation of `Eq` for `S`, \
+                 but 2 conflicting implementations exist",
+            ]
+        );
+        let label = diagnostics[1].labels.last().unwrap();
+        assert_eq!(label.message, "in a default method inherited by this impl");
+        assert_eq!(label.span.start, input.find("impl D").unwrap());
+    }
+}
@@ -0,0 +1,1127 @@
+//! Name resolution.
+//! Builds a tree of scopes from the `mod` blocks of a program, and resolves every identifier to
+//! the definition or local variable it refers to.
+//!
+//! Names are looked up lexically: a simple name is searched for in the enclosing mod, then in
+//! each mod around it, up to the top level. The first segment of a path is looked up the same way,
+//! while every following segment must be defined directly in the mod before it.
+//!
+//! Definitions are private to their mod unless marked `pub`. A path naming a definition that is not
+//! visible from where it is written is an error, see [`Resolution::is_visible`].
+//!
+//! Type parameters of structs, functions and impls are in scope in their definition, and shadow
+//! structs of the same name. Their bounds name traits.
+//!
+//! In expressions, a path that does not name a function may name a method of a named impl, like
+//! `M::C::foo`. This calls the method of that impl, whatever impls are in scope, including a
+//! default method of its trait the impl leaves out.
+
+use crate::ast::{
+    Definition, Expr, Function, Generic, Ident, Impl, Item, Parameter, Program, Span, TraitMethod,
+    Type, Visibility,
+};
+use crate::diagnostic::Diagnostic;
+use crate::node::{Cons, Nil, Node};
+use crate::session::Session;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct DefId(pub usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefKind {
+    Mod,
+    Struct,
+    Trait,
+    Impl,
+    Func,
+}
+
+impl DefKind {
+    #[must_use]
+    pub fn namespace(self) -> Namespace {
+        match self {
+            DefKind::Mod => Namespace::Module,
+            DefKind::Struct | DefKind::Trait => Namespace::Type,
+            DefKind::Impl => Namespace::Impl,
+            DefKind::Func => Namespace::Value,
+        }
+    }
+
+    #[must_use]
+    pub fn describe(self) -> &'static str {
+        match self {
+            DefKind::Mod => "module",
+            DefKind::Struct => "struct",
+            DefKind::Trait => "trait",
+            DefKind::Impl => "impl",
+            DefKind::Func => "function",
+        }
+    }
+}
+
+/// Definitions of different kinds may share a name, as long as they live in different namespaces
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Namespace {
+    Module,
+    Type,
+    Value,
+    Impl,
+}
+
+impl Namespace {
+    #[must_use]
+    pub fn describe(self) -> &'static str {
+        match self {
+            Namespace::Module => "module",
+            Namespace::Type => "type",
+            Namespace::Value => "value",
+            Namespace::Impl => "impl",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Def<'a> {
+    pub kind: DefKind,
+    /// `None` for anonymous impls
+    pub name: Option<&'a str>,
+    /// The enclosing mod, `None` at the top level
+    pub parent: Option<DefId>,
+    pub item: &'a Item,
+}
+
+/// What an identifier refers to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Res {
+    Def(DefId),
+    /// A parameter or `let` binding, identified by the span of its binding identifier.
+    /// Qualified identifiers starting with a local, like `a.foo`, access a field or method of it
+    Local(Span),
+    /// A method of a named impl, called explicitly like `M::C::foo()`
+    ImplMethod(DefId),
+    /// A type parameter, identified by the span of its declaring identifier
+    Param(Span),
+}
+
+#[derive(Debug)]
+pub enum LookupError {
+    /// No definition for the segment at the given index
+    NotFound(usize),
+    /// The segment at the given index has several definitions
+    Ambiguous(usize, Vec<DefId>),
+}
+
+#[derive(Debug, Default)]
+struct ModScope<'a> {
+    names: HashMap<Namespace, HashMap<&'a str, Vec<DefId>>>,
+}
+
+impl ModScope<'_> {
+    fn get(&self, namespace: Namespace, name: &str) -> Option<&Vec<DefId>> {
+        self.names.get(&namespace)?.get(name)
+    }
+}
+
+/// The definition an identifier expression, or the function a call names, resolved to. `None` for
+/// other expressions, and for names of parameters and `let` bindings
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResolvedName(pub Option<DefId>);
+
+/// The mod an expression is written in, `None` at the top level
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Scope(pub Option<DefId>);
+
+/// What [`annotate`] needs of a [`Resolution`], without borrowing the program
+#[derive(Debug, Default)]
+pub struct ResolvedNames {
+    /// Definitions, keyed by the span of the identifier resolved to them
+    defs: HashMap<Span, DefId>,
+    /// Mods, keyed by the span of their item
+    mods: HashMap<Span, DefId>,
+}
+
+pub struct Resolution<'a> {
+    pub defs: Vec<Def<'a>>,
+    root: ModScope<'a>,
+    scopes: HashMap<DefId, ModScope<'a>>,
+    items: HashMap<Span, DefId>,
+    names: HashMap<Span, Res>,
+    /// The impls named by the `use` items of each mod, `None` for the top level
+    uses: HashMap<Option<DefId>, Vec<&'a Ident>>,
+    /// Every type parameter, keyed by the span of its identifier
+    generics: HashMap<Span, &'a Generic>,
+}
+
+impl<'a> Resolution<'a> {
+    #[must_use]
+    pub fn def(&self, id: DefId) -> &Def<'a> {
+        &self.defs[id.0]
+    }
+
+    /// The definition created for an item of the resolved program
+    ///
+    /// # Panics
+    /// If the item is not part of the resolved program
+    #[must_use]
+    pub fn item_def(&self, item: &Item) -> DefId {
+        self.items[&item.span]
+    }
+
+    /// What the identifier was resolved to, if anything
+    #[must_use]
+    pub fn res(&self, ident: &Ident) -> Option<Res> {
+        self.names.get(&ident.span()).copied()
+    }
+
+    /// The definition the identifier was resolved to, if it was resolved to a definition
+    #[must_use]
+    pub fn res_def(&self, ident: &Ident) -> Option<DefId> {
+        match self.res(ident)? {
+            Res::Def(id) => Some(id),
+            Res::Local(_) | Res::ImplMethod(_) | Res::Param(_) => None,
+        }
+    }
+
+    /// Every identifier resolved to a definition, and every mod, to annotate the program with
+    #[must_use]
+    pub fn resolved_names(&self) -> ResolvedNames {
+        let defs = self
+            .names
+            .iter()
+            .filter_map(|(&span, res)| match res {
+                Res::Def(id) => Some((span, *id)),
+                _ => None,
+            })
+            .collect();
+        let mods = self
+            .items
+            .iter()
+            .filter(|(_, &id)| self.def(id).kind == DefKind::Mod)
+            .map(|(&span, &id)| (span, id))
+            .collect();
+        ResolvedNames { defs, mods }
+    }
+
+    /// The trait and struct of a trait impl, if both were resolved
+    #[must_use]
+    pub fn impl_of(&self, id: DefId) -> Option<(DefId, DefId)> {
+        match &self.def(id).item.definition {
+            Definition::Impl(Impl::Trait(_, _, trait_ident, target, _)) => {
+                Some((self.res_def(trait_ident)?, self.res_def(target.ident()?)?))
+            }
+            _ => None,
+        }
+    }
+
+    /// The type parameter declared by the identifier at `span`
+    #[must_use]
+    pub fn generic(&self, span: Span) -> Option<&'a Generic> {
+        self.generics.get(&span).copied()
+    }
+
+    /// The method `name` declared by the trait `id`
+    #[must_use]
+    pub fn trait_method(&self, id: DefId, name: &str) -> Option<&'a TraitMethod> {
+        match &self.def(id).item.definition {
+            Definition::Trait(.., methods) => methods.iter().find(|m| m.ident.name() == name),
+            _ => None,
+        }
+    }
+
+    /// The resolved supertraits of the trait `id`, in source order
+    pub fn supertraits(&self, id: DefId) -> impl Iterator<Item = DefId> + '_ {
+        let supertraits = match &self.def(id).item.definition {
+            Definition::Trait(_, supertraits, _) => supertraits.as_slice(),
+            _ => &[],
+        };
+        supertraits.iter().filter_map(|ident| self.res_def(ident))
+    }
+
+    /// Whether the definition can be named from `module`. It must be `pub` or defined in `module`
+    /// or a mod around it, and so must every mod it is nested in
+    #[must_use]
+    pub fn is_visible(&self, id: DefId, module: Option<DefId>) -> bool {
+        let def = self.def(id);
+        let accessible =
+            def.item.visibility == Visibility::Public || self.is_within(module, def.parent);
+        accessible
+            && def
+                .parent
+                .is_none_or(|parent| self.is_visible(parent, module))
+    }
+
+    /// Whether `module` is `ancestor` or nested in it
+    fn is_within(&self, mut module: Option<DefId>, ancestor: Option<DefId>) -> bool {
+        loop {
+            if module == ancestor {
+                return true;
+            }
+            let Some(id) = module else {
+                return false;
+            };
+            module = self.def(id).parent;
+        }
+    }
+
+    /// The impl names of the `use` items directly in `module`, in source order
+    #[must_use]
+    pub fn module_uses(&self, module: Option<DefId>) -> &[&'a Ident] {
+        self.uses.get(&module).map_or(&[], Vec::as_slice)
+    }
+
+    /// Ids of all definitions of the given kind, in source order
+    pub fn defs_of_kind(&self, kind: DefKind) -> impl Iterator<Item = DefId> + '_ {
+        (0..self.defs.len())
+            .map(DefId)
+            .filter(move |&id| self.def(id).kind == kind)
+    }
+
+    /// Fully qualified path of a definition, like `A::B::C`. Anonymous impls are shown as `{impl}`
+    #[must_use]
+    pub fn path(&self, id: DefId) -> String {
+        let def = self.def(id);
+        let name = def.name.unwrap_or("{impl}");
+        match def.parent {
+            Some(parent) => format!("{}::{name}", self.path(parent)),
+            None => name.to_string(),
+        }
+    }
+
+    /// Looks up `segments` in `namespace`, starting from the scope of `module`
+    ///
+    /// # Errors
+    /// Returns [`LookupError`] if a segment is missing or ambiguous
+    pub fn lookup(
+        &self,
+        module: Option<DefId>,
+        segments: &[&str],
+        namespace: Namespace,
+    ) -> Result<DefId, LookupError> {
+        let Some((last, path)) = segments.split_last() else {
+            return Err(LookupError::NotFound(0));
+        };
+
+        let mut module = module;
+        for (index, segment) in path.iter().enumerate() {
+            let found = if index == 0 {
+                self.lookup_lexical(module, segment, Namespace::Module)
+            } else {
+                self.scope(module).get(Namespace::Module, segment)
+            };
+            module = Some(unique(found, index)?);
+        }
+
+        let found = if path.is_empty() {
+            self.lookup_lexical(module, last, namespace)
+        } else {
+            self.scope(module).get(namespace, last)
+        };
+        unique(found, path.len())
+    }
+
+    fn lookup_lexical(
+        &self,
+        mut module: Option<DefId>,
+        name: &str,
+        namespace: Namespace,
+    ) -> Option<&Vec<DefId>> {
+        loop {
+            if let Some(found) = self.scope(module).get(namespace, name) {
+                return Some(found);
+            }
+            module = self.def(module?).parent;
+        }
+    }
+
+    fn scope(&self, module: Option<DefId>) -> &ModScope<'a> {
+        match module {
+            Some(id) => &self.scopes[&id],
+            None => &self.root,
+        }
+    }
+}
+
+fn unique(found: Option<&Vec<DefId>>, index: usize) -> Result<DefId, LookupError> {
+    match found.map(Vec::as_slice) {
+        None | Some([]) => Err(LookupError::NotFound(index)),
+        Some([id]) => Ok(*id),
+        Some(ids) => Err(LookupError::Ambiguous(index, ids.to_vec())),
+    }
+}
+
+/// Attaches a [`ResolvedName`] and a [`Scope`] to every expression of the program
+///
+/// # Panics
+/// If `names` were not resolved for `program`
+#[must_use]
+pub fn annotate(
+    program: Program,
+    names: &ResolvedNames,
+) -> Program<Cons<Scope, Cons<ResolvedName, Nil>>> {
+    /// Pushes the scope of every expression of `items`, in the order [`Program::annotate`]
+    /// visits them
+    fn item_scopes(
+        items: &[Item],
+        module: Option<DefId>,
+        names: &ResolvedNames,
+        scopes: &mut Vec<Scope>,
+    ) {
+        for item in items {
+            match &item.definition {
+                Definition::Struct(..) | Definition::Use(_) => {}
+                Definition::Trait(.., methods) => {
+                    for body in methods.iter().filter_map(|m| m.body.as_ref()) {
+                        expr_scopes(body, module, scopes);
+                    }
+                }
+                Definition::Impl(Impl::Trait(.., fs) | Impl::Struct(_, fs)) => {
+                    for function in fs {
+                        expr_scopes(&function.body, module, scopes);
+                    }
+                }
+                Definition::Mod(_, children) => {
+                    item_scopes(children, names.mods.get(&item.span).copied(), names, scopes);
+                }
+                Definition::Func(function) => expr_scopes(&function.body, module, scopes),
+            }
+        }
+    }
+
+    fn expr_scopes(expr: &Node<Expr>, module: Option<DefId>, scopes: &mut Vec<Scope>) {
+        scopes.push(Scope(module));
+        for child in expr.node.children() {
+            expr_scopes(child, module, scopes);
+        }
+    }
+
+    let mut all = Vec::new();
+    item_scopes(&program.nodes, None, names, &mut all);
+    let program = program.annotate(&mut |expr| {
+        ResolvedName(match &expr.node {
+            Expr::Ident(ident) | Expr::Invocation(ident, _) => {
+                names.defs.get(&ident.span()).copied()
+            }
+            _ => None,
+        })
+    });
+    let mut all = all.into_iter();
+    program.annotate(&mut |_| {
+        all.next()
+            .expect("a scope for every expression of the program")
+    })
+}
+
+/// Builds the scope tree of the program and resolves every identifier in it.
+/// Unknown and ambiguous names are reported to the session, and left unresolved
+pub fn resolve<'a>(session: &mut Session, program: &'a Program) -> Resolution<'a> {
+    let mut resolution = Resolution {
+        defs: Vec::new(),
+        root: ModScope::default(),
+        scopes: HashMap::new(),
+        items: HashMap::new(),
+        names: HashMap::new(),
+        uses: HashMap::new(),
+        generics: HashMap::new(),
+    };
+    collect(&mut resolution, &program.nodes, None);
+
+    let mut resolver = Resolver {
+        session,
+        resolution,
+        module: None,
+        locals: Vec::new(),
+        generics: Vec::new(),
+    };
+    resolver.resolve_items(&program.nodes);
+    resolver.resolution
+}
+
+fn collect<'a>(resolution: &mut Resolution<'a>, items: &'a [Item], parent: Option<DefId>) {
+    for item in items {
+        let (kind, name) = match &item.definition {
+            Definition::Use(ident) => {
+                resolution.uses.entry(parent).or_default().push(ident);
+                continue;
+            }
+            Definition::Struct(ident, ..) => (DefKind::Struct, Some(ident)),
+            Definition::Trait(ident, ..) => (DefKind::Trait, Some(ident)),
+            Definition::Impl(Impl::Trait(name, ..)) => (DefKind::Impl, name.as_ref()),
+            Definition::Impl(Impl::Struct(..)) => (DefKind::Impl, None),
+            Definition::Mod(ident, _) => (DefKind::Mod, Some(ident)),
+            Definition::Func(function) => (DefKind::Func, Some(&function.ident)),
+        };
+
+        let id = DefId(resolution.defs.len());
+        let name = name.map(Ident::name);
+        resolution.defs.push(Def {
+            kind,
+            name,
+            parent,
+            item,
+        });
+        resolution.items.insert(item.span, id);
+
+        if let Some(name) = name {
+            let scope = match parent {
+                Some(parent) => resolution.scopes.get_mut(&parent).unwrap(),
+                None => &mut resolution.root,
+            };
+            scope
+                .names
+                .entry(kind.namespace())
+                .or_default()
+                .entry(name)
+                .or_default()
+                .push(id);
+        }
+
+        if let Definition::Mod(_, children) = &item.definition {
+            resolution.scopes.insert(id, ModScope::default());
+            collect(resolution, children, Some(id));
+        }
+    }
+}
+
+struct Resolver<'a, 's, 'r> {
+    session: &'s mut Session<'r>,
+    resolution: Resolution<'a>,
+    /// The mod currently being resolved, `None` at the top level
+    module: Option<DefId>,
+    /// Local variables in scope, one frame per block, innermost last
+    locals: Vec<Vec<&'a Ident>>,
+    /// Type parameters in scope, innermost last
+    generics: Vec<&'a Generic>,
+}
+
+impl<'a> Resolver<'a, '_, '_> {
+    fn resolve_items(&mut self, items: &'a [Item]) {
+        for item in items {
+            match &item.definition {
+                Definition::Struct(_, generics, fields) => {
+                    self.enter_generics(generics);
+                    for field in fields {
+                        self.resolve_type(&field.field_type);
+                    }
+                    self.generics.clear();
+                }
+                Definition::Trait(_, supertraits, methods) => {
+                    for supertrait in supertraits {
+                        self.resolve_ident(supertrait, Namespace::Type, DefKind::Trait);
+                    }
+                    for method in methods {
+                        self.resolve_parameters(&method.parameters);
+                        self.resolve_type(&method.return_type);
+                        if let Some(body) = &method.body {
+                            self.resolve_body(method.receiver.as_ref(), &method.parameters, body);
+                        }
+                    }
+                }
+                Definition::Impl(Impl::Trait(_, generics, trait_ident, target, functions)) => {
+                    self.enter_generics(generics);
+                    self.resolve_ident(trait_ident, Namespace::Type, DefKind::Trait);
+                    // The implementing type is always a struct, never a type parameter
+                    if let Some(ident) = target.ident() {
+                        self.resolve_ident(ident, Namespace::Type, DefKind::Struct);
+                    }
+                    if let Type::Applied(_, arguments) = target {
+                        for argument in arguments {
+                            self.resolve_type(argument);
+                        }
+                    }
+                    for function in functions {
+                        self.resolve_function(function);
+                    }
+                    self.generics.clear();
+                }
+                Definition::Impl(Impl::Struct(struct_ident, functions)) => {
+                    self.resolve_ident(struct_ident, Namespace::Type, DefKind::Struct);
+                    for function in functions {
+                        self.resolve_function(function);
+                    }
+                }
+                Definition::Mod(_, children) => {
+                    let outer = self.module.replace(self.resolution.item_def(item));
+                    self.resolve_items(children);
+                    self.module = outer;
+                }
+                Definition::Func(function) => self.resolve_function(function),
+                Definition::Use(ident) => {
+                    self.resolve_ident(ident, Namespace::Impl, DefKind::Impl);
+                }
+            }
+        }
+    }
+
+    fn resolve_function(&mut self, function: &'a Function) {
+        let depth = self.generics.len();
+        self.enter_generics(&function.generics);
+        self.resolve_parameters(&function.parameters);
+        if let Some(return_type) = &function.return_type {
+            self.resolve_type(return_type);
+        }
+        self.resolve_body(
+            function.receiver.as_ref(),
+            &function.parameters,
+            &function.body,
+        );
+        self.generics.truncate(depth);
+    }
+
+    /// Brings type parameters into scope, and resolves their bounds
+    fn enter_generics(&mut self, generics: &'a [Generic]) {
+        for generic in generics {
+            for bound in &generic.bounds {
+                self.resolve_ident(bound, Namespace::Type, DefKind::Trait);
+            }
+            self.resolution
+                .generics
+                .insert(generic.ident.span(), generic);
+            self.generics.push(generic);
+        }
+    }
+
+    fn resolve_body(
+        &mut self,
+        receiver: Option<&'a Ident>,
+        parameters: &'a [Parameter],
+        body: &'a Node<Expr>,
+    ) {
+        let parameters = parameters.iter().map(|p| &p.ident);
+        self.locals
+            .push(receiver.into_iter().chain(parameters).collect());
+        self.resolve_expr(body);
+        self.locals.pop();
+    }
+
+    fn resolve_parameters(&mut self, parameters: &'a [Parameter]) {
+        for parameter in parameters {
+            self.resolve_type(&parameter.parameter_type);
+        }
+    }
+
+    fn resolve_type(&mut self, t: &'a Type) {
+        match t {
+            Type::Unit | Type::I32 | Type::F32 => {}
+            Type::Function(parameters, return_type) => {
+                self.resolve_parameters(parameters);
+                self.resolve_type(return_type);
+            }
+            Type::Ident(ident) => {
+                let param =
+                    self.generics.iter().rev().find(
+                        |g| matches!(ident, Ident::Simple(name, _) if g.ident.name() == name),
+                    );
+                if let Some(param) = param {
+                    let res = Res::Param(param.ident.span());
+                    self.resolution.names.insert(ident.span(), res);
+                } else {
+                    self.resolve_ident(ident, Namespace::Type, DefKind::Struct);
+                }
+            }
+            Type::Applied(ident, arguments) => {
+                self.resolve_ident(ident, Namespace::Type, DefKind::Struct);
+                for argument in arguments {
+                    self.resolve_type(argument);
+                }
+            }
+            Type::Indexed(ident, bindings) => {
+                self.resolve_ident(ident, Namespace::Type, DefKind::Struct);
+                for binding in bindings {
+                    self.resolve_ident(&binding.trait_ident, Namespace::Type, DefKind::Trait);
+                    self.resolve_ident(&binding.impl_ident, Namespace::Impl, DefKind::Impl);
+                }
+            }
+        }
+    }
+
+    fn resolve_expr(&mut self, expr: &'a Node<Expr>) {
+        match &expr.node {
+            Expr::Literal(_) => {}
+            Expr::Ident(ident) => self.resolve_value(ident),
+            Expr::Paren(e) | Expr::Unary(_, e) => self.resolve_expr(e),
+            Expr::Binary(left, _, right) => {
+                self.resolve_expr(left);
+                self.resolve_expr(right);
+            }
+            Expr::Let(ident, t, value) => {
+                if let Some(t) = t {
+                    self.resolve_type(t);
+                }
+                self.resolve_expr(value);
+                self.locals
+                    .last_mut()
+                    .expect("let is always inside a function body")
+                    .push(ident);
+            }
+            Expr::If(cond, yes, no) => {
+                self.resolve_expr(cond);
+                self.resolve_expr(yes);
+                if let Some(no) = no {
+                    self.resolve_expr(no);
+                }
+            }
+            Expr::Block(statements, tail) => {
+                self.locals.push(Vec::new());
+                for statement in statements {
+                    self.resolve_expr(statement);
+                }
+                if let Some(tail) = tail {
+                    self.resolve_expr(tail);
+                }
+                self.locals.pop();
+            }
+            Expr::Invocation(ident, args) => {
+                self.resolve_value(ident);
+                for arg in args {
+                    self.resolve_expr(arg);
+                }
+            }
+            // Methods are only known once the type of the receiver is
+            Expr::MethodCall(receiver, _, args) => {
+                self.resolve_expr(receiver);
+                for arg in args {
+                    self.resolve_expr(arg);
+                }
+            }
+            Expr::Use(ident, e) => {
+                self.resolve_ident(ident, Namespace::Impl, DefKind::Impl);
+                self.resolve_expr(e);
+            }
+            Expr::StructInit(ident, fields) => {
+                self.resolve_ident(ident, Namespace::Type, DefKind::Struct);
+                for (_, value) in fields {
+                    self.resolve_expr(value);
+                }
+            }
+        }
+    }
+
+    /// Resolves an identifier in expression position. Its first segment may name a local
+    fn resolve_value(&mut self, ident: &Ident) {
+        let first = ident.segments()[0];
+        let local = self
+            .locals
+            .iter()
+            .rev()
+            .flat_map(|frame| frame.iter().rev())
+            .find(|local| local.name() == first)
+            .map(|local| local.span());
+
+        match local {
+            Some(local) => {
+                let res = Res::Local(local);
+                self.resolution.names.insert(ident.span(), res);
+            }
+            None if self.resolve_impl_method(ident) => {}
+            None => self.resolve_ident(ident, Namespace::Value, DefKind::Func),
+        }
+    }
+
+    /// Resolves a path like `M::C::foo` to the method `foo` of the impl `M::C`. Returns whether the
+    /// path names an impl, and not a function
+    fn resolve_impl_method(&mut self, ident: &Ident) -> bool {
+        let segments = ident.segments();
+        let Some((method, path)) = segments.split_last() else {
+            return false;
+        };
+        if path.is_empty()
+            || self
+                .resolution
+                .lookup(self.module, &segments, Namespace::Value)
+                .is_ok()
+        {
+            return false;
+        }
+        let Ok(id) = self.resolution.lookup(self.module, path, Namespace::Impl) else {
+            return false;
+        };
+        if !self.resolution.is_visible(id, self.module) {
+            let error = self.private(id);
+            self.session
+                .emit(Diagnostic::error(error).with_span(ident.span()));
+            return true;
+        }
+
+        let def = self.resolution.def(id);
+        let provided = match &def.item.definition {
+            Definition::Impl(Impl::Trait(_, _, trait_ident, _, functions)) => {
+                // The trait may not be resolved yet if the impl comes later in the source
+                let trait_id =
+                    self.resolution
+                        .lookup(def.parent, &trait_ident.segments(), Namespace::Type);
+                functions.iter().any(|f| f.ident.name() == *method)
+                    || trait_id
+                        .ok()
+                        .and_then(|trait_id| self.resolution.trait_method(trait_id, method))
+                        .is_some_and(|m| m.body.is_some())
+            }
+            _ => false,
+        };
+        if provided {
+            self.resolution
+                .names
+                .insert(ident.span(), Res::ImplMethod(id));
+        } else {
+            let error = format!(
+                "no method `{method}` in impl `{}`",
+                self.resolution.path(id)
+            );
+            self.session
+                .emit(Diagnostic::error(error).with_span(ident.span()));
+        }
+        true
+    }
+
+    fn resolve_ident(&mut self, ident: &Ident, namespace: Namespace, expected: DefKind) {
+        let segments = ident.segments();
+        let error = match self.resolution.lookup(self.module, &segments, namespace) {
+            Ok(id) if !self.resolution.is_visible(id, self.module) => self.private(id),
+            Ok(id) if self.resolution.def(id).kind == expected => {
+                self.resolution.names.insert(ident.span(), Res::Def(id));
+                return;
+            }
+            Ok(id) => format!(
+                "expected {}, found {} `{}`",
+                expected.describe(),
+                self.resolution.def(id).kind.describe(),
+                self.resolution.path(id)
+            ),
+            Err(LookupError::NotFound(index)) if index + 1 < segments.len() => {
+                let module = segments[..=index].join("::");
+                format!("cannot find module `{module}` in this scope")
+            }
+            Err(LookupError::NotFound(_)) => format!(
+                "cannot find {} `{ident}` in this scope",
+                namespace.describe()
+            ),
+            Err(LookupError::Ambiguous(index, candidates)) => {
+                let candidates = candidates
+                    .iter()
+                    .map(|&id| format!("`{}`", self.resolution.path(id)))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!(
+                    "`{}` is ambiguous, it could refer to any of {candidates}",
+                    segments[..=index].join("::")
+                )
+            }
+        };
+        self.session
+            .emit(Diagnostic::error(error).with_span(ident.span()));
+    }
+
+    fn private(&self, id: DefId) -> String {
+        let def = self.resolution.def(id);
+        format!(
+            "{} `{}` is private",
+            def.kind.describe(),
+            self.resolution.path(id)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    fn errors(input: &str) -> Vec<String> {
+        let program = parse(input).unwrap();
+        let mut diagnostics = Vec::new();
+        resolve(&mut Session::new(&mut diagnostics), &program);
+        diagnostics.into_iter().map(|d| d.message).collect()
+    }
+
+    #[test]
+    fn test_resolve_paths() {
+        let input = r"
+mod A {
+    pub struct A {}
+    pub mod Inner {
+        pub fn f(a: A) -> A { a }
+    }
+}
+
+mod B {
+    pub trait B {
+        fn foo() -> i32
+    }
+}
+
+mod C {
+    impl C of B::B for A::A {
+        fn foo() -> i32 { 1 }
+    }
+    fn g(a: A::A) { use C in A::Inner::f(a) }
+}
+";
+        let program = parse(input).unwrap();
+        let mut diagnostics = Vec::new();
+        let resolution = resolve(&mut Session::new(&mut diagnostics), &program);
+        assert!(diagnostics.is_empty(), "{diagnostics:?}");
+
+        let paths: Vec<_> = (0..resolution.defs.len())
+            .map(|id| resolution.path(DefId(id)))
+            .collect();
+        let expected = [
+            "A",
+            "A::A",
+            "A::Inner",
+            "A::Inner::f",
+            "B",
+            "B::B",
+            "C",
+            "C::C",
+            "C::g",
+        ];
+        assert_eq!(paths, expected);
+
+        let Definition::Mod(_, items) = &program.nodes[2].definition else {
+            panic!("Expected mod");
+        };
+        let Definition::Impl(Impl::Trait(_, _, trait_ident, target, _)) = &items[0].definition
+        else {
+            panic!("Expected trait impl");
+        };
+        assert_eq!(resolution.res(trait_ident), Some(Res::Def(DefId(5))));
+        assert_eq!(
+            resolution.res(target.ident().unwrap()),
+            Some(Res::Def(DefId(1)))
+        );
+    }
+
+    #[test]
+    fn test_resolve_locals() {
+        let input = r"
+struct S { a: i32 }
+fn f(a: S) -> i32 {
+    let b = a.a;
+    { let c = b; c };
+    f(a) + b
+}
+";
+        assert!(errors(input).is_empty());
+
+        let input = r"
+fn f() -> i32 {
+    { let c = 1; c };
+    c
+}
+";
+        assert_eq!(errors(input), ["cannot find value `c` in this scope"]);
+    }
+
+    #[test]
+    fn test_resolve_errors() {
+        let input = r"
+mod A {
+    struct A {}
+    struct A {}
+}
+trait T {}
+impl T for A::B { fn f() {} }
+impl A::A for T { fn f() {} }
+fn f(a: A::A) {}
+fn g(b: B::A) {}
+fn h(t: T) { use X in f(t) }
+";
+        assert_eq!(
+            errors(input),
+            [
+                "cannot find type `A::B` in this scope",
+                "`A::A` is ambiguous, it could refer to any of `A::A`, `A::A`",
+                "expected struct, found trait `T`",
+                "`A::A` is ambiguous, it could refer to any of `A::A`, `A::A`",
+                "cannot find module `B` in this scope",
+                "expected struct, found trait `T`",
+                "cannot find impl `X` in this scope",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_resolve_impl_methods() {
+        let input = r"
+struct S {}
+trait T { fn foo() -> i32 }
+mod M {
+    pub impl C of T for S { fn foo() -> i32 { 1 } }
+    pub fn foo() -> i32 { 2 }
+}
+fn f() -> i32 { M::C::foo() + M::foo() }
+fn g() -> i32 { M::C::bar() + M::D::foo() }
+";
+        let program = parse(input).unwrap();
+        let mut diagnostics = Vec::new();
+        let resolution = resolve(&mut Session::new(&mut diagnostics), &program);
+        let messages: Vec<_> = diagnostics.iter().map(|d| &d.message).collect();
+        assert_eq!(
+            messages,
+            [
+                "no method `bar` in impl `M::C`",
+                "cannot find module `M::D` in this scope",
+            ]
+        );
+
+        let Definition::Func(function) = &program.nodes[3].definition else {
+            panic!("Expected function");
+        };
+        let Expr::Block(_, Some(tail)) = &function.body.node else {
+            panic!("Expected block with tail");
+        };
+        let Expr::Binary(left, _, right) = &tail.node else {
+            panic!("Expected binary expression");
+        };
+        let (Expr::Invocation(method, _), Expr::Invocation(function, _)) =
+            (&left.node, &right.node)
+        else {
+            panic!("Expected invocations");
+        };
+        assert_eq!(resolution.res(method), Some(Res::ImplMethod(DefId(3))));
+        assert_eq!(resolution.res(function), Some(Res::Def(DefId(4))));
+    }
+
+    #[test]
+    fn test_resolve_visibility() {
+        let input = r"
+mod A {
+    pub struct S {}
+    struct Hidden {}
+    fn helper(s: S) -> S { s }
+    pub fn f(s: S) -> S { helper(s) }
+    mod Private {
+        pub fn g() {}
+    }
+    pub mod Inner {
+        fn h(h: Hidden) { super_helper() }
+        fn super_helper() { A::Private::g() }
+    }
+}
+fn main(s: A::S, h: A::Hidden) {
+    A::f(s);
+    A::helper(s);
+    A::Private::g();
+}
+";
+        assert_eq!(
+            errors(input),
+            [
+                "struct `A::Hidden` is private",
+                "function `A::helper` is private",
+                "function `A::Private::g` is private",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_resolve_shadowing() {
+        let input = r"
+struct S {}
+mod M {
+    struct S {}
+    fn f(s: S) -> S { s }
+}
+";
+        let program = parse(input).unwrap();
+        let mut diagnostics = Vec::new();
+        let resolution = resolve(&mut Session::new(&mut diagnostics), &program);
+
+        let Definition::Mod(_, items) = &program.nodes[1].definition else {
+            panic!("Expected mod");
+        };
+        let Definition::Func(function) = &items[1].definition else {
+            panic!("Expected function");
+        };
+        let Type::Ident(ident) = &function.parameters[0].parameter_type else {
+            panic!("Expected struct type");
+        };
+        let id = resolution.res_def(ident).unwrap();
+        assert_eq!(resolution.path(id), "M::S");
+    }
+
+    #[test]
+    fn test_resolve_generics() {
+        let input = r"
+struct T {}
+trait Show {}
+struct List<T: Show> { head: T }
+fn f<T>(a: T, l: List<T>) -> T { a }
+fn g(a: T) -> U { a }
+impl<U: Missing> Show for List<U> {}
+";
+        assert_eq!(
+            errors(input),
+            [
+                "cannot find type `U` in this scope",
+                "cannot find type `Missing` in this scope",
+            ]
+        );
+
+        let program = parse(input).unwrap();
+        let mut diagnostics = Vec::new();
+        let resolution = resolve(&mut Session::new(&mut diagnostics), &program);
+        let Definition::Func(function) = &program.nodes[3].definition else {
+            panic!("Expected function");
+        };
+        let Type::Ident(ident) = &function.parameters[0].parameter_type else {
+            panic!("Expected type parameter");
+        };
+        let Some(Res::Param(span)) = resolution.res(ident) else {
+            panic!("Expected type parameter");
+        };
+        assert_eq!(span, function.generics[0].ident.span());
+        assert!(resolution.generic(span).unwrap().bounds.is_empty());
+
+        let Definition::Func(function) = &program.nodes[4].definition else {
+            panic!("Expected function");
+        };
+        let Type::Ident(ident) = &function.parameters[0].parameter_type else {
+            panic!("Expected struct type");
+        };
+        assert_eq!(resolution.res(ident), Some(Res::Def(DefId(0))));
+    }
+
+    #[test]
+    fn test_annotate() {
+        let input = r"
+fn f(a: i32) -> i32 { a }
+mod M {
+    fn g() -> i32 { f(1) }
+}
+";
+        let program = parse(input).unwrap();
+        let mut diagnostics = Vec::new();
+        let names = resolve(&mut Session::new(&mut diagnostics), &program).resolved_names();
+        let program = annotate(program, &names);
+
+        let Definition::Func(function) = &program.nodes[0].definition else {
+            panic!("Expected function");
+        };
+        let Expr::Block(_, Some(tail)) = &function.body.node else {
+            panic!("Expected block with tail");
+        };
+        // `a` is a parameter, not a definition
+        assert_eq!(tail.get_annotation::<ResolvedName>(), &ResolvedName(None));
+        assert_eq!(tail.get_annotation::<Scope>(), &Scope(None));
+
+        let Definition::Mod(_, items) = &program.nodes[1].definition else {
+            panic!("Expected mod");
+        };
+        let Definition::Func(function) = &items[0].definition else {
+            panic!("Expected function");
+        };
+        let Expr::Block(_, Some(call)) = &function.body.node else {
+            panic!("Expected block with tail");
+        };
+        assert_eq!(
+            call.get_annotation::<ResolvedName>(),
+            &ResolvedName(Some(DefId(0)))
+        );
+        assert_eq!(call.get_annotation::<Scope>(), &Scope(Some(DefId(1))));
+        let Expr::Invocation(_, args) = &call.node else {
+            panic!("Expected call");
+        };
+        assert_eq!(args[0].get_annotation::<Scope>(), &Scope(Some(DefId(1))));
+    }
+}
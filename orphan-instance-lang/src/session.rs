@@ -0,0 +1,54 @@
+//! A compilation session, routing everything a pass wants to report into a caller-supplied sink.
+
+use crate::diagnostic::{Diagnostic, DiagnosticSink};
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ParseOptions {
+    /// Forward the parser input and resulting AST to [`DiagnosticSink::trace`]
+    pub trace: bool,
+}
+
+pub struct Session<'a> {
+    pub options: ParseOptions,
+    sink: &'a mut dyn DiagnosticSink,
+    errors: usize,
+}
+
+impl<'a> Session<'a> {
+    pub fn new(sink: &'a mut dyn DiagnosticSink) -> Self {
+        Session::with_options(ParseOptions::default(), sink)
+    }
+
+    pub fn with_options(options: ParseOptions, sink: &'a mut dyn DiagnosticSink) -> Self {
+        Session {
+            options,
+            sink,
+            errors: 0,
+        }
+    }
+
+    pub fn emit(&mut self, diagnostic: Diagnostic) {
+        if diagnostic.is_error() {
+            self.errors += 1;
+        }
+        self.sink.emit(diagnostic);
+    }
+
+    /// Sends trace output to the sink. The message is only built when tracing is enabled
+    pub fn trace(&mut self, message: impl FnOnce() -> String) {
+        if self.options.trace {
+            self.sink.trace(&message());
+        }
+    }
+
+    /// Number of errors emitted through this session so far
+    #[must_use]
+    pub fn error_count(&self) -> usize {
+        self.errors
+    }
+
+    #[must_use]
+    pub fn has_errors(&self) -> bool {
+        self.errors > 0
+    }
+}
@@ -0,0 +1,2263 @@
+//! Type checking.
+//! Computes the type of every expression of a resolved program, and reports expressions whose
+//! type does not match what their context requires.
+//!
+//! There is no boolean type. Conditions, comparisons and logical operators use `i32`, where zero
+//! is false.
+//!
+//! Types of `let` bindings and return types may be left out. They are inferred by unification
+//! within the function bodies: a missing return type is inferred from the body of its function
+//! alone, and calls use the inferred type. The body of a function without a return type is
+//! checked before the first body calling it, so bodies follow the call graph rather than the
+//! source order. Only a function calling itself, directly or through other functions, needs its
+//! return type written out. A method implementing a trait method takes the return type the trait
+//! declares. A mismatch with an inferred type also points at where it was inferred.
+//!
+//! An impl-indexed type like `Set[Hash = C]` is a struct bound to some of its trait impls. A
+//! struct literal takes the type it is expected to have, so the impls are chosen when the value is
+//! built. From then on every call on the value uses them, and a `use` selecting another impl of
+//! the same trait is rejected. Values bound to different impls have different types.
+//!
+//! Generic structs and functions are instantiated with type arguments inferred from the values
+//! given to them. A type argument must implement the traits bounding its parameter, and a value
+//! of a type parameter can only call the methods of those traits.
+//!
+//! A method call `x.m(args)` passes `x` as the `self` parameter of the method it resolves to. The
+//! receiver may be any atom, like `(a + b).m()` or `f().m()`. The method of an inherent impl of
+//! the struct of `x` takes priority over trait methods. Methods without `self` cannot be called
+//! on a value. Called by path, like `C::m(x, args)`, a method takes its receiver as the first
+//! argument.
+
+use crate::ast::{
+    BinOp, Definition, Expr, Function, Generic, Ident, Impl, ImplBinding, Item, Literal, Parameter,
+    Program, Span, StructField, TraitMethod, Type, UnOp,
+};
+use crate::diagnostic::{Diagnostic, Label};
+use crate::instance::ResolvedImpl;
+use crate::node::{Cons, Excludes, Nil, Node};
+use crate::resolve::{DefId, DefKind, Res, Resolution, ResolvedName, Scope};
+use crate::session::Session;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::ptr;
+
+/// The type of an expression while checking
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Ty {
+    Unit,
+    I32,
+    F32,
+    /// A struct, its type arguments, and the impls it is bound to, sorted
+    Struct(DefId, Vec<Ty>, Vec<DefId>),
+    /// A type parameter, identified by the span of its declaring identifier
+    Param(Span),
+    /// Whatever struct implements the trait, the type of `self` in its default methods
+    Implementor(DefId),
+    /// A type variable, solved by unification
+    Infer(usize),
+    /// The type of an expression that already produced an error. It is compatible with
+    /// everything, so a single mistake is only reported once
+    Error,
+}
+
+impl Ty {
+    fn is_numeric(&self) -> bool {
+        matches!(self, Ty::I32 | Ty::F32)
+    }
+
+    fn has_error(&self) -> bool {
+        match self {
+            Ty::Error => true,
+            Ty::Struct(_, arguments, _) => arguments.iter().any(Ty::has_error),
+            _ => false,
+        }
+    }
+
+    /// The type variables the type mentions
+    fn vars(&self) -> Vec<usize> {
+        match self {
+            Ty::Infer(var) => vec![*var],
+            Ty::Struct(_, arguments, _) => arguments.iter().flat_map(Ty::vars).collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct TypeError {
+    pub kind: TypeErrorKind,
+    pub span: Span,
+    /// Where a type involved in the error was inferred
+    pub origin: Option<Label>,
+}
+
+#[derive(Debug)]
+pub enum TypeErrorKind {
+    Mismatch {
+        expected: Type,
+        found: Type,
+    },
+    BinaryOperands {
+        op: &'static str,
+        left: Type,
+        right: Type,
+    },
+    UnaryOperand {
+        op: &'static str,
+        operand: Type,
+    },
+    ArgumentCount {
+        expected: usize,
+        found: usize,
+    },
+    /// A function used as a value. Functions can only be called
+    NotAValue {
+        name: String,
+    },
+    NoField {
+        ty: Type,
+        field: String,
+    },
+    MissingFields {
+        ty: Type,
+        fields: Vec<String>,
+    },
+    DuplicateField {
+        field: String,
+    },
+    NoMethod {
+        ty: Type,
+        method: String,
+    },
+    /// A method without `self` called on a value
+    NoReceiver {
+        method: String,
+    },
+    /// An impl-indexed type binding an impl that does not implement the trait for the struct
+    InvalidBinding {
+        ty: Type,
+        trait_name: String,
+        impl_name: String,
+    },
+    DuplicateBinding {
+        trait_name: String,
+    },
+    /// A generic struct given the wrong number of type arguments
+    TypeArgumentCount {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+    /// A type argument not implementing a trait bounding its parameter
+    UnsatisfiedBound {
+        ty: Type,
+        trait_name: String,
+    },
+    /// A type that is needed before anything could be inferred about it
+    AnnotationsNeeded,
+    /// A recursive call of a function without a return type
+    ReturnTypeNeeded {
+        name: String,
+    },
+    /// A `use` selecting another impl than the one the receiver of a call is bound to
+    UnstableImpl {
+        ty: Type,
+        bound: String,
+        selected: String,
+    },
+    /// A method call whose candidate traits declare the method with different signatures
+    AmbiguousMethod {
+        method: String,
+        traits: Vec<String>,
+    },
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            TypeErrorKind::Mismatch { expected, found } => {
+                write!(
+                    f,
+                    "mismatched types, expected `{expected}`, found `{found}`"
+                )
+            }
+            TypeErrorKind::BinaryOperands { op, left, right } => {
+                write!(f, "cannot apply `{op}` to `{left}` and `{right}`")
+            }
+            TypeErrorKind::UnaryOperand { op, operand } => {
+                write!(f, "cannot apply `{op}` to `{operand}`")
+            }
+            TypeErrorKind::ArgumentCount { expected, found } => {
+                let s = if *expected == 1 { "" } else { "s" };
+                write!(f, "expected {expected} argument{s}, found {found}")
+            }
+            TypeErrorKind::NotAValue { name } => {
+                write!(f, "expected value, found function `{name}`")
+            }
+            TypeErrorKind::NoField { ty, field } => write!(f, "no field `{field}` on `{ty}`"),
+            TypeErrorKind::MissingFields { ty, fields } => {
+                let fields = fields
+                    .iter()
+                    .map(|field| format!("`{field}`"))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                write!(f, "missing fields {fields} in initializer of `{ty}`")
+            }
+            TypeErrorKind::DuplicateField { field } => {
+                write!(f, "field `{field}` specified more than once")
+            }
+            TypeErrorKind::NoMethod { ty, method } => {
+                write!(f, "no method named `{method}` found for `{ty}`")
+            }
+            TypeErrorKind::NoReceiver { method } => {
+                write!(f, "method `{method}` has no `self` parameter")
+            }
+            TypeErrorKind::InvalidBinding {
+                ty,
+                trait_name,
+                impl_name,
+            } => {
+                write!(
+                    f,
+                    "`{impl_name}` is not an impl of `{trait_name}` for `{ty}`"
+                )
+            }
+            TypeErrorKind::DuplicateBinding { trait_name } => {
+                write!(f, "trait `{trait_name}` is bound more than once")
+            }
+            TypeErrorKind::TypeArgumentCount {
+                name,
+                expected,
+                found,
+            } => {
+                let s = if *expected == 1 { "" } else { "s" };
+                write!(
+                    f,
+                    "struct `{name}` takes {expected} type argument{s}, found {found}"
+                )
+            }
+            TypeErrorKind::UnsatisfiedBound { ty, trait_name } => {
+                write!(f, "the trait bound `{ty}: {trait_name}` is not satisfied")
+            }
+            TypeErrorKind::AnnotationsNeeded => write!(f, "type annotations needed"),
+            TypeErrorKind::ReturnTypeNeeded { name } => {
+                write!(
+                    f,
+                    "return type of `{name}` must be written out to call it recursively"
+                )
+            }
+            TypeErrorKind::UnstableImpl {
+                ty,
+                bound,
+                selected,
+            } => {
+                write!(
+                    f,
+                    "value of type `{ty}` is bound to impl `{bound}`, but `{selected}` is selected"
+                )
+            }
+            TypeErrorKind::AmbiguousMethod { method, traits } => {
+                let traits = traits
+                    .iter()
+                    .map(|name| format!("`{name}`"))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                write!(
+                    f,
+                    "method `{method}` is declared with different signatures by traits {traits}"
+                )
+            }
+        }
+    }
+}
+
+/// A call of a trait method, whose impl is picked by [`instance`](crate::instance) resolution
+#[derive(Debug)]
+pub struct MethodCall {
+    /// The struct the method is called on
+    pub receiver: DefId,
+    pub method: String,
+    /// Type arguments of the receiver's struct
+    pub arguments: Vec<Ty>,
+    /// Impls the type of the receiver is bound to
+    pub bound: Vec<DefId>,
+}
+
+pub struct TypeckResults {
+    /// The id of every expression of the program checked
+    pub exprs: ExprIds,
+    /// The type of every expression, indexed by its id. `None` for the expressions whose type has
+    /// an error
+    pub types: Vec<Option<Type>>,
+    /// Trait method calls, keyed by the invocation
+    pub method_calls: HashMap<ExprId, MethodCall>,
+    /// Method calls on `self` in default methods, keyed by the invocation, with the traits
+    /// declaring the method. Their impl is picked in every impl inheriting the default method
+    pub self_calls: HashMap<ExprId, Vec<DefId>>,
+    /// The traits bounding the type parameters of each call of a generic function, with the type
+    /// the parameter was instantiated with, keyed by the invocation
+    pub bounds: HashMap<ExprId, Vec<(DefId, Ty)>>,
+    pub errors: Vec<TypeError>,
+}
+
+/// Parameter and return types of a function. The `self` parameter of a method is not one of the
+/// parameters, a method call passes the value it is called on instead
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Signature {
+    /// Whether the function is a method taking `self`
+    pub receiver: bool,
+    pub parameters: Vec<Ty>,
+    pub output: Ty,
+}
+
+impl Signature {
+    pub(crate) fn new(
+        resolution: &Resolution,
+        parameters: &[Parameter],
+        output: Option<&Type>,
+    ) -> Self {
+        Signature {
+            receiver: false,
+            parameters: parameters
+                .iter()
+                .map(|p| lower(resolution, &p.parameter_type))
+                .collect(),
+            output: output.map_or(Ty::Unit, |t| lower(resolution, t)),
+        }
+    }
+
+    pub(crate) fn of_method(resolution: &Resolution, method: &TraitMethod) -> Self {
+        Signature {
+            receiver: method.receiver.is_some(),
+            ..Signature::new(resolution, &method.parameters, Some(&method.return_type))
+        }
+    }
+
+    pub(crate) fn of_function(resolution: &Resolution, function: &Function) -> Self {
+        Signature {
+            receiver: function.receiver.is_some(),
+            ..Signature::new(
+                resolution,
+                &function.parameters,
+                function.return_type.as_ref(),
+            )
+        }
+    }
+
+    /// Renders the signature like `fn(self, i32, A::A) -> f32`
+    pub(crate) fn display(&self, resolution: &Resolution) -> String {
+        let receiver = self.receiver.then(|| "self".to_string());
+        let parameters = receiver
+            .into_iter()
+            .chain(
+                self.parameters
+                    .iter()
+                    .map(|ty| to_type(resolution, ty).to_string()),
+            )
+            .collect::<Vec<_>>()
+            .join(", ");
+        format!("fn({parameters}) -> {}", to_type(resolution, &self.output))
+    }
+}
+
+/// Type checks every function in the program. Errors are reported to the session as well as
+/// returned in the results
+pub fn check(session: &mut Session, resolution: &Resolution, program: &Program) -> TypeckResults {
+    let mut checker = Checker {
+        session,
+        resolution,
+        exprs: ExprIds::new(program),
+        types: HashMap::new(),
+        locals: HashMap::new(),
+        module: None,
+        uses: Vec::new(),
+        receiver: Ty::Error,
+        vars: Vec::new(),
+        returns: HashMap::new(),
+        unchecked: unchecked_functions(resolution),
+        origins: HashMap::new(),
+        inferred_locals: HashMap::new(),
+        method_calls: HashMap::new(),
+        self_calls: HashMap::new(),
+        bounds: HashMap::new(),
+        errors: Vec::new(),
+    };
+    checker.check_items(&program.nodes);
+
+    // Type variables may have been solved after the types mentioning them were recorded
+    let mut types = vec![None; checker.exprs.spans.len()];
+    let mut unsolved = HashSet::new();
+    let exprs = std::mem::take(&mut checker.exprs);
+    for (id, span) in exprs.iter() {
+        let ty = checker
+            .types
+            .get(&id)
+            .map_or(Ty::Error, |ty| checker.resolved(ty));
+        let vars = ty.vars();
+        if !vars.is_empty() {
+            // Reported once for each variable, where it is first left unsolved
+            if vars.iter().any(|&var| unsolved.insert(var)) {
+                checker.error(TypeErrorKind::AnnotationsNeeded, span);
+            }
+        } else if !ty.has_error() {
+            types[id.0] = Some(to_type(resolution, &ty));
+        }
+    }
+    let mut method_calls = std::mem::take(&mut checker.method_calls);
+    for call in method_calls.values_mut() {
+        call.arguments = call
+            .arguments
+            .iter()
+            .map(|ty| checker.resolved(ty))
+            .collect();
+    }
+    let mut bounds = std::mem::take(&mut checker.bounds);
+    for (_, ty) in bounds.values_mut().flatten() {
+        *ty = checker.resolved(ty);
+    }
+    TypeckResults {
+        exprs,
+        types,
+        method_calls,
+        self_calls: checker.self_calls,
+        bounds,
+        errors: checker.errors,
+    }
+}
+
+/// The annotation state of a program checked by the [`driver`](crate::driver): every expression
+/// has its [`Type`], the impl it calls, the definition it names and its scope
+pub type Typed = Cons<Type, Cons<ResolvedImpl, Cons<Scope, Cons<ResolvedName, Nil>>>>;
+
+/// Attaches the computed [`Type`] to every expression node of the program
+///
+/// # Errors
+/// Returns the program unchanged if some expression has no type, as type checking reported an
+/// error for it
+///
+/// # Panics
+/// If `results` were not computed for `program`
+pub fn annotate<S>(
+    program: Program<S>,
+    results: TypeckResults,
+) -> Result<Program<Cons<Type, S>>, Program<S>>
+where
+    S: Excludes<Type>,
+{
+    if results.types.iter().any(Option::is_none) {
+        return Err(program);
+    }
+    let mut types = results.types.into_iter().flatten();
+    Ok(program.annotate(&mut |_| {
+        types
+            .next()
+            .expect("the results have a type for every expression of the program")
+    }))
+}
+
+/// Identifies an expression of a program by its position in the order [`Program::annotate`]
+/// visits them: bodies in source order, each expression before its sub-expressions. Unlike spans,
+/// ids tell apart every node, including those not created by the parser
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ExprId(pub usize);
+
+/// The [`ExprId`] of every expression of a program, and its span
+#[derive(Debug, Default)]
+pub struct ExprIds {
+    ids: HashMap<*const Node<Expr>, ExprId>,
+    spans: Vec<Span>,
+}
+
+impl ExprIds {
+    #[must_use]
+    pub fn new(program: &Program) -> Self {
+        fn number(expr: &Node<Expr>, exprs: &mut ExprIds) {
+            exprs
+                .ids
+                .insert(ptr::from_ref(expr), ExprId(exprs.spans.len()));
+            exprs.spans.push(expr.span());
+            for child in expr.node.children() {
+                number(child, exprs);
+            }
+        }
+
+        let mut exprs = ExprIds::default();
+        for body in program.bodies() {
+            number(body, &mut exprs);
+        }
+        exprs
+    }
+
+    /// The id of `expr`
+    ///
+    /// # Panics
+    /// If `expr` is not an expression of the program the ids were computed for
+    #[must_use]
+    pub fn id(&self, expr: &Node<Expr>) -> ExprId {
+        self.ids[&ptr::from_ref(expr)]
+    }
+
+    /// The span of the expression with the id `id`
+    #[must_use]
+    pub fn span(&self, id: ExprId) -> Span {
+        self.spans[id.0]
+    }
+
+    /// Every id, in order, with the span of its expression
+    pub fn iter(&self) -> impl Iterator<Item = (ExprId, Span)> + '_ {
+        self.spans
+            .iter()
+            .enumerate()
+            .map(|(id, &span)| (ExprId(id), span))
+    }
+}
+
+struct Checker<'a, 'r, 's, 'ss> {
+    session: &'s mut Session<'ss>,
+    resolution: &'r Resolution<'a>,
+    exprs: ExprIds,
+    /// The type of each expression checked
+    types: HashMap<ExprId, Ty>,
+    /// Types of parameters and `let` bindings, keyed by the span of the binding identifier
+    locals: HashMap<Span, Ty>,
+    /// The mod of the function being checked, `None` at the top level
+    module: Option<DefId>,
+    /// Impls selected by the enclosing `use` expressions, innermost last
+    uses: Vec<DefId>,
+    /// The type of `self` in the methods being checked
+    receiver: Ty,
+    /// The solution of each type variable, and where it was solved
+    vars: Vec<Option<(Ty, Span)>>,
+    /// Return types inferred for the functions without one, keyed by the span of their name. A
+    /// function is only in it once its body is checked
+    returns: HashMap<Span, Ty>,
+    /// The functions without a return type whose body is not being checked yet, keyed by the span
+    /// of their name, with the function or impl defining them
+    unchecked: HashMap<Span, (DefId, &'a Function)>,
+    /// Where the type of a use of a `let` binding without a type was inferred, keyed by the span
+    /// of the use
+    origins: HashMap<Span, Span>,
+    /// Spans of the values of `let` bindings without a type, keyed by the span of the binding
+    inferred_locals: HashMap<Span, Span>,
+    method_calls: HashMap<ExprId, MethodCall>,
+    self_calls: HashMap<ExprId, Vec<DefId>>,
+    bounds: HashMap<ExprId, Vec<(DefId, Ty)>>,
+    errors: Vec<TypeError>,
+}
+
+impl<'a> Checker<'a, '_, '_, '_> {
+    fn check_items(&mut self, items: &[Item]) {
+        for item in items {
+            match &item.definition {
+                Definition::Struct(_, _, fields) => {
+                    for field in fields {
+                        self.check_type(&field.field_type);
+                    }
+                }
+                Definition::Trait(.., methods) => {
+                    self.receiver = Ty::Implementor(self.resolution.item_def(item));
+                    for method in methods {
+                        if let Some(body) = &method.body {
+                            self.check_type(&method.return_type);
+                            let output = lower(self.resolution, &method.return_type);
+                            self.check_body(
+                                method.receiver.as_ref(),
+                                &method.parameters,
+                                &output,
+                                body,
+                            );
+                        }
+                    }
+                }
+                Definition::Use(_) => {}
+                Definition::Impl(
+                    definition @ Impl::Trait(_, _, trait_ident, target, functions),
+                ) => {
+                    self.check_type(target);
+                    self.receiver = self.impl_receiver(definition);
+                    for function in functions {
+                        let declared = self.declared_output(trait_ident, function);
+                        self.check_function(function, declared);
+                    }
+                }
+                Definition::Impl(definition @ Impl::Struct(_, functions)) => {
+                    self.receiver = self.impl_receiver(definition);
+                    for function in functions {
+                        self.check_function(function, None);
+                    }
+                }
+                Definition::Mod(_, children) => {
+                    let outer = self.module.replace(self.resolution.item_def(item));
+                    self.check_items(children);
+                    self.module = outer;
+                }
+                Definition::Func(function) => self.check_function(function, None),
+            }
+        }
+    }
+
+    /// The type of `self` in the methods of an impl
+    fn impl_receiver(&self, definition: &Impl) -> Ty {
+        match definition {
+            Impl::Trait(.., target, _) => lower(self.resolution, target),
+            Impl::Struct(struct_ident, _) => match self.resolution.res_def(struct_ident) {
+                Some(id) => {
+                    let arguments = vec![Ty::Error; self.struct_generics(id).len()];
+                    Ty::Struct(id, arguments, Vec::new())
+                }
+                None => Ty::Error,
+            },
+        }
+    }
+
+    /// The return type the trait of `trait_ident` declares for the method `function` implements,
+    /// and where. A left out return type is the declared one
+    fn declared_output(&self, trait_ident: &Ident, function: &Function) -> Option<(Ty, Span)> {
+        let declared = self.declared(trait_ident, function.ident.name())?;
+        Some((
+            lower(self.resolution, &declared.return_type),
+            declared.ident.span(),
+        ))
+    }
+
+    /// Checks the body of `function`, inferring its return type if it is left out. `declared` is
+    /// the return type declared by the trait method it implements, if any, and where
+    fn check_function(&mut self, function: &Function, declared: Option<(Ty, Span)>) {
+        // A call may have needed the body checked already, see [`Checker::output`]
+        let name = function.ident.span();
+        if function.return_type.is_none() && self.unchecked.remove(&name).is_none() {
+            return;
+        }
+        let output = if let Some(output) = &function.return_type {
+            self.check_type(output);
+            lower(self.resolution, output)
+        } else {
+            self.vars.push(None);
+            let var = self.vars.len() - 1;
+            if let Some((ty, span)) = declared {
+                self.solve(var, &ty, span);
+            }
+            Ty::Infer(var)
+        };
+        self.check_body(
+            function.receiver.as_ref(),
+            &function.parameters,
+            &output,
+            &function.body,
+        );
+
+        if function.return_type.is_none() {
+            if let Ty::Infer(var) = self.resolved(&output) {
+                self.solve(var, &Ty::Error, name);
+                self.error(TypeErrorKind::AnnotationsNeeded, name);
+            }
+            self.returns.insert(name, output);
+        }
+    }
+
+    /// Checks the body of `function`, defined by the function or impl `id`, in the middle of
+    /// checking a body calling it
+    fn check_callee(&mut self, id: DefId, function: &Function) {
+        let def = self.resolution.def(id);
+        let (receiver, declared) = match &def.item.definition {
+            Definition::Impl(definition) => {
+                let declared = match definition {
+                    Impl::Trait(_, _, trait_ident, ..) => {
+                        self.declared_output(trait_ident, function)
+                    }
+                    Impl::Struct(..) => None,
+                };
+                (self.impl_receiver(definition), declared)
+            }
+            _ => (Ty::Error, None),
+        };
+        let module = std::mem::replace(&mut self.module, def.parent);
+        let uses = std::mem::take(&mut self.uses);
+        let receiver = std::mem::replace(&mut self.receiver, receiver);
+        self.check_function(function, declared);
+        self.module = module;
+        self.uses = uses;
+        self.receiver = receiver;
+    }
+
+    fn check_body(
+        &mut self,
+        receiver: Option<&Ident>,
+        parameters: &[Parameter],
+        output: &Ty,
+        body: &Node<Expr>,
+    ) {
+        if let Some(receiver) = receiver {
+            self.locals.insert(receiver.span(), self.receiver.clone());
+        }
+        for parameter in parameters {
+            self.check_type(&parameter.parameter_type);
+            let ty = lower(self.resolution, &parameter.parameter_type);
+            self.locals.insert(parameter.ident.span(), ty);
+        }
+        self.expect(body, output);
+    }
+
+    /// The return type of `function` for a call at `span`. A left out one is only known once the
+    /// body of the function is checked, which is done first unless the call is recursive
+    fn output(&mut self, function: &Function, span: Span) -> Ty {
+        if let Some(output) = &function.return_type {
+            return lower(self.resolution, output);
+        }
+        let name = function.ident.span();
+        if let Some(&(id, function)) = self.unchecked.get(&name) {
+            self.check_callee(id, function);
+        }
+        if let Some(ty) = self.returns.get(&name) {
+            return ty.clone();
+        }
+        let kind = TypeErrorKind::ReturnTypeNeeded {
+            name: function.ident.name().to_string(),
+        };
+        self.error(kind, span);
+        Ty::Error
+    }
+
+    /// Replaces the solved type variables in `ty` by their solutions
+    fn resolved(&self, ty: &Ty) -> Ty {
+        match ty {
+            Ty::Infer(var) => match &self.vars[*var] {
+                Some((solution, _)) => self.resolved(solution),
+                None => ty.clone(),
+            },
+            Ty::Struct(id, arguments, bound) => Ty::Struct(
+                *id,
+                arguments.iter().map(|ty| self.resolved(ty)).collect(),
+                bound.clone(),
+            ),
+            _ => ty.clone(),
+        }
+    }
+
+    /// Solves the unsolved type variable `var` with `ty`, at `span`
+    fn solve(&mut self, var: usize, ty: &Ty, span: Span) {
+        // A type containing its own variable has no solution
+        let ty = if self.mentions(ty, var) {
+            Ty::Error
+        } else {
+            ty.clone()
+        };
+        self.vars[var] = Some((ty, span));
+    }
+
+    fn mentions(&self, ty: &Ty, var: usize) -> bool {
+        match self.resolved(ty) {
+            Ty::Infer(other) => other == var,
+            Ty::Struct(_, arguments, _) => arguments.iter().any(|ty| self.mentions(ty, var)),
+            _ => false,
+        }
+    }
+
+    /// Where the type variable `ty` is, if it is one, was solved with a type that is not a
+    /// variable itself
+    fn origin(&self, ty: &Ty) -> Option<Span> {
+        let Ty::Infer(var) = ty else {
+            return None;
+        };
+        let (solution, span) = self.vars[*var].as_ref()?;
+        self.origin(solution).or(Some(*span))
+    }
+
+    /// Reports generic structs given the wrong number of type arguments, type arguments not
+    /// satisfying their bounds, and impl-indexed types binding impls that do not belong to them
+    fn check_type(&mut self, t: &Type) {
+        let bindings = match t {
+            Type::Ident(ident) => return self.check_arguments(ident, &[]),
+            Type::Applied(ident, arguments) => {
+                for argument in arguments {
+                    self.check_type(argument);
+                }
+                return self.check_arguments(ident, arguments);
+            }
+            Type::Indexed(ident, bindings) => {
+                self.check_arguments(ident, &[]);
+                bindings
+            }
+            Type::Unit | Type::I32 | Type::F32 | Type::Function(..) => return,
+        };
+        let Some(struct_id) = t.ident().and_then(|ident| self.resolution.res_def(ident)) else {
+            return;
+        };
+        let mut traits = HashSet::new();
+        for ImplBinding {
+            trait_ident,
+            impl_ident,
+        } in bindings
+        {
+            let (Some(trait_id), Some(impl_id)) = (
+                self.resolution.res_def(trait_ident),
+                self.resolution.res_def(impl_ident),
+            ) else {
+                continue;
+            };
+            let trait_name = self.resolution.path(trait_id);
+            if self.resolution.impl_of(impl_id) != Some((trait_id, struct_id)) {
+                let kind = TypeErrorKind::InvalidBinding {
+                    ty: to_type(
+                        self.resolution,
+                        &Ty::Struct(struct_id, Vec::new(), Vec::new()),
+                    ),
+                    trait_name,
+                    impl_name: self.resolution.path(impl_id),
+                };
+                self.error(kind, impl_ident.span());
+            } else if !traits.insert(trait_id) {
+                let kind = TypeErrorKind::DuplicateBinding { trait_name };
+                self.error(kind, trait_ident.span());
+            }
+        }
+    }
+
+    /// Checks the number of type arguments given to the struct of `ident`, and their bounds
+    fn check_arguments(&mut self, ident: &Ident, arguments: &[Type]) {
+        let Some(id) = self.resolution.res_def(ident) else {
+            return;
+        };
+        let generics = self.struct_generics(id);
+        if generics.len() != arguments.len() {
+            let kind = TypeErrorKind::TypeArgumentCount {
+                name: self.resolution.path(id),
+                expected: generics.len(),
+                found: arguments.len(),
+            };
+            return self.error(kind, ident.span());
+        }
+        for (generic, argument) in generics.iter().zip(arguments) {
+            let ty = lower(self.resolution, argument);
+            self.check_bounds(generic, &ty, ident.span());
+        }
+    }
+
+    /// Reports the traits bounding `generic` that `ty` does not implement
+    fn check_bounds(&mut self, generic: &Generic, ty: &Ty, span: Span) {
+        for bound in &generic.bounds {
+            let Some(trait_id) = self.resolution.res_def(bound) else {
+                continue;
+            };
+            if !self.implements(ty, trait_id) {
+                let kind = TypeErrorKind::UnsatisfiedBound {
+                    ty: to_type(self.resolution, ty),
+                    trait_name: self.resolution.path(trait_id),
+                };
+                self.error(kind, span);
+            }
+        }
+    }
+
+    /// Whether `ty` implements the trait `trait_id`. A struct needs a visible impl whose own
+    /// bounds its type arguments satisfy, and a type parameter must be bounded by the trait or by
+    /// a trait it is a supertrait of. So must the trait `self` implements in a default method
+    fn implements(&self, ty: &Ty, trait_id: DefId) -> bool {
+        match &self.resolved(ty) {
+            // Nothing is known about an unsolved type to contradict the bound
+            Ty::Error | Ty::Infer(_) => true,
+            Ty::Param(span) => self.resolution.generic(*span).is_some_and(|generic| {
+                generic
+                    .bounds
+                    .iter()
+                    .filter_map(|bound| self.resolution.res_def(bound))
+                    .any(|bound| implied_traits(self.resolution, bound).contains(&trait_id))
+            }),
+            Ty::Implementor(implemented) => {
+                implied_traits(self.resolution, *implemented).contains(&trait_id)
+            }
+            Ty::Struct(id, arguments, _) => {
+                self.resolution.defs_of_kind(DefKind::Impl).any(|impl_id| {
+                    self.resolution.is_visible(impl_id, self.module)
+                        && self.resolution.impl_of(impl_id) == Some((trait_id, *id))
+                        && self.impl_applies(impl_id, arguments)
+                })
+            }
+            Ty::Unit | Ty::I32 | Ty::F32 => false,
+        }
+    }
+
+    /// Whether the type arguments of a struct satisfy the bounds of a generic impl for it
+    fn impl_applies(&self, impl_id: DefId, arguments: &[Ty]) -> bool {
+        let Definition::Impl(Impl::Trait(_, generics, _, target, _)) =
+            &self.resolution.def(impl_id).item.definition
+        else {
+            return false;
+        };
+        let inferred = impl_arguments(self.resolution, impl_id, target, arguments);
+        generics.iter().all(|generic| {
+            let ty = inferred.get(&generic.ident.span()).unwrap_or(&Ty::Error);
+            generic
+                .bounds
+                .iter()
+                .filter_map(|bound| self.resolution.res_def(bound))
+                .all(|bound| self.implements(ty, bound))
+        })
+    }
+
+    /// Checks `expr`, and reports an error if its type is not `expected`
+    fn expect(&mut self, expr: &Node<Expr>, expected: &Ty) {
+        let found = self.check_expr(expr);
+        if let (Ty::Struct(id, _, bound), Ty::Struct(found_id, _, found_bound)) = (expected, &found)
+        {
+            if id == found_id
+                && !bound.is_empty()
+                && found_bound.is_empty()
+                && self.bind(expr, expected)
+            {
+                return;
+            }
+        }
+        self.unify(expected, &found, value_span(expr));
+    }
+
+    /// Checks `expr` against `expected`, which may mention type parameters of a generic item
+    /// being instantiated. Those not `inferred` yet are inferred from the type of `expr`
+    fn expect_generic(
+        &mut self,
+        expr: &Node<Expr>,
+        expected: &Ty,
+        inferred: &mut HashMap<Span, Ty>,
+    ) {
+        let found = self.check_expr(expr);
+        infer(expected, &found, inferred);
+        self.unify(&substitute(expected, inferred), &found, value_span(expr));
+    }
+
+    /// Gives a struct literal producing the value of `expr` the impl-indexed type it is expected
+    /// to have. Returns whether there was one
+    fn bind(&mut self, expr: &Node<Expr>, ty: &Ty) -> bool {
+        let bound = match &expr.node {
+            Expr::Block(_, Some(tail)) => self.bind(tail, ty),
+            Expr::StructInit(..) => true,
+            _ => false,
+        };
+        if bound {
+            self.record(expr, ty.clone());
+        }
+        bound
+    }
+
+    /// Reports an error if the types differ, and returns the one to continue with. An unsolved
+    /// type variable on either side is solved with the other
+    fn unify(&mut self, expected: &Ty, found: &Ty, span: Span) -> Ty {
+        // Where either type was inferred, to explain a mismatch
+        let origin = match self.origin(expected) {
+            Some(origin) => Some((origin, true)),
+            None => self
+                .origin(found)
+                .or_else(|| self.origins.get(&span).copied())
+                .map(|origin| (origin, false)),
+        };
+        let (expected, found) = (self.resolved(expected), self.resolved(found));
+        match (&expected, &found) {
+            (Ty::Infer(var), Ty::Infer(other)) if var == other => return expected,
+            (Ty::Infer(var), _) => {
+                self.solve(*var, &found, span);
+                return found;
+            }
+            (_, Ty::Infer(var)) => {
+                self.solve(*var, &expected, span);
+                return expected;
+            }
+            _ => {}
+        }
+        if compatible(&expected, &found) {
+            return if expected.has_error() {
+                found
+            } else {
+                expected
+            };
+        }
+
+        let origin = origin.map(|(span, is_expected)| {
+            let ty = if is_expected { &expected } else { &found };
+            let message = format!("`{}` inferred here", to_type(self.resolution, ty));
+            Label { span, message }
+        });
+        let kind = TypeErrorKind::Mismatch {
+            expected: to_type(self.resolution, &expected),
+            found: to_type(self.resolution, &found),
+        };
+        self.error_with_origin(kind, span, origin);
+        Ty::Error
+    }
+
+    fn check_expr(&mut self, expr: &Node<Expr>) -> Ty {
+        let ty = match &expr.node {
+            Expr::Literal(Literal::I32(_)) => Ty::I32,
+            Expr::Literal(Literal::F32(_)) => Ty::F32,
+            Expr::Ident(ident) => self.check_ident(ident),
+            Expr::Paren(e) => self.check_expr(e),
+            Expr::Use(ident, e) => {
+                let selected = self.resolution.res_def(ident);
+                self.uses.extend(selected);
+                let ty = self.check_expr(e);
+                if selected.is_some() {
+                    self.uses.pop();
+                }
+                ty
+            }
+            Expr::Unary(op, e) => {
+                let operand = self.check_expr(e);
+                self.check_unary(op, operand, expr.span())
+            }
+            Expr::Binary(left, op, right) => {
+                let left = self.check_expr(left);
+                let right = self.check_expr(right);
+                self.check_binary(op, &left, &right, expr.span())
+            }
+            Expr::Let(ident, t, value) => {
+                let ty = if let Some(t) = t {
+                    self.check_type(t);
+                    let ty = lower(self.resolution, t);
+                    self.expect(value, &ty);
+                    ty
+                } else {
+                    self.inferred_locals.insert(ident.span(), value_span(value));
+                    self.check_expr(value)
+                };
+                self.locals.insert(ident.span(), ty);
+                Ty::Unit
+            }
+            Expr::If(cond, yes, no) => {
+                self.expect(cond, &Ty::I32);
+                if let Some(no) = no {
+                    let yes = self.check_expr(yes);
+                    let found = self.check_expr(no);
+                    self.unify(&yes, &found, value_span(no))
+                } else {
+                    self.expect(yes, &Ty::Unit);
+                    Ty::Unit
+                }
+            }
+            Expr::Block(statements, tail) => {
+                for statement in statements {
+                    self.check_expr(statement);
+                }
+                tail.as_ref().map_or(Ty::Unit, |tail| self.check_expr(tail))
+            }
+            Expr::Invocation(ident, args) => self.check_invocation(ident, args, expr),
+            Expr::MethodCall(receiver, method, args) => {
+                let receiver = self.check_expr(receiver);
+                let call = self.exprs.id(expr);
+                let signature =
+                    self.method_signature(&receiver, method.name(), method.span(), call);
+                self.check_call(signature, &[], args, call, expr.span())
+            }
+            Expr::StructInit(ident, fields) => self.check_struct_init(ident, fields, expr.span()),
+        };
+        let ty = self.resolved(&ty);
+        self.record(expr, ty.clone());
+        ty
+    }
+
+    /// Records `ty` as the type of `expr`
+    fn record(&mut self, expr: &Node<Expr>, ty: Ty) {
+        self.types.insert(self.exprs.id(expr), ty);
+    }
+
+    fn check_unary(&mut self, op: &UnOp, operand: Ty, span: Span) -> Ty {
+        match (op, &operand) {
+            (_, Ty::Error) => Ty::Error,
+            (UnOp::Neg, ty) if ty.is_numeric() => operand,
+            (UnOp::Not, Ty::I32) => Ty::I32,
+            (UnOp::Not, Ty::Infer(_)) => self.unify(&Ty::I32, &operand, span),
+            _ => {
+                let kind = TypeErrorKind::UnaryOperand {
+                    op: op.symbol(),
+                    operand: to_type(self.resolution, &operand),
+                };
+                self.error(kind, span);
+                Ty::Error
+            }
+        }
+    }
+
+    fn check_binary(&mut self, op: &BinOp, left: &Ty, right: &Ty, span: Span) -> Ty {
+        if *left == Ty::Error || *right == Ty::Error {
+            return Ty::Error;
+        }
+        // Both operands have the same type, so an unsolved one has the type of the other
+        let unified;
+        let (left, right) = if matches!(left, Ty::Infer(_)) || matches!(right, Ty::Infer(_)) {
+            unified = self.unify(left, right, span);
+            match unified {
+                Ty::Error => return Ty::Error,
+                Ty::Infer(_) => {
+                    self.error(TypeErrorKind::AnnotationsNeeded, span);
+                    return Ty::Error;
+                }
+                _ => (&unified, &unified),
+            }
+        } else {
+            (left, right)
+        };
+
+        let ty = match op {
+            BinOp::Mult | BinOp::Div | BinOp::Modulo | BinOp::Plus | BinOp::Minus => {
+                (left == right && left.is_numeric()).then(|| left.clone())
+            }
+            BinOp::LT | BinOp::LTE | BinOp::GT | BinOp::GTE | BinOp::Equal | BinOp::NotEqual => {
+                (left == right && left.is_numeric()).then_some(Ty::I32)
+            }
+            BinOp::And | BinOp::Or => (*left == Ty::I32 && *right == Ty::I32).then_some(Ty::I32),
+        };
+        ty.unwrap_or_else(|| {
+            let kind = TypeErrorKind::BinaryOperands {
+                op: op.symbol(),
+                left: to_type(self.resolution, left),
+                right: to_type(self.resolution, right),
+            };
+            self.error(kind, span);
+            Ty::Error
+        })
+    }
+
+    /// A local, or a field of a local like `a.b.c`
+    fn check_ident(&mut self, ident: &Ident) -> Ty {
+        match self.resolution.res(ident) {
+            Some(Res::Local(binding)) => {
+                let local = self.locals.get(&binding).cloned().unwrap_or(Ty::Error);
+                let segments = ident.segments();
+                if let (Some(&value), 1) = (self.inferred_locals.get(&binding), segments.len()) {
+                    self.origins.insert(ident.span(), value);
+                }
+                self.check_fields(&local, &segments[1..], ident.span())
+            }
+            Some(Res::Def(id)) => {
+                let name = self.resolution.path(id);
+                self.error(TypeErrorKind::NotAValue { name }, ident.span());
+                Ty::Error
+            }
+            Some(Res::ImplMethod(_)) => {
+                let name = ident.to_string();
+                self.error(TypeErrorKind::NotAValue { name }, ident.span());
+                Ty::Error
+            }
+            Some(Res::Param(_)) | None => Ty::Error,
+        }
+    }
+
+    /// Follows a chain of field accesses starting from a value of type `ty`
+    fn check_fields(&mut self, ty: &Ty, fields: &[&str], span: Span) -> Ty {
+        let mut ty = self.resolved(ty);
+        for field in fields {
+            let found = match &ty {
+                Ty::Error => return Ty::Error,
+                Ty::Infer(_) => {
+                    self.error(TypeErrorKind::AnnotationsNeeded, span);
+                    return Ty::Error;
+                }
+                Ty::Struct(id, arguments, _) => self
+                    .struct_fields(*id)
+                    .iter()
+                    .find(|f| f.ident.name() == *field)
+                    .map(|f| self.field_type(*id, arguments, f)),
+                _ => None,
+            };
+            ty = found.unwrap_or_else(|| {
+                let kind = TypeErrorKind::NoField {
+                    ty: to_type(self.resolution, &ty),
+                    field: (*field).to_string(),
+                };
+                self.error(kind, span);
+                Ty::Error
+            });
+        }
+        ty
+    }
+
+    fn check_invocation(&mut self, ident: &Ident, args: &[Node<Expr>], call: &Node<Expr>) -> Ty {
+        let (span, call) = (call.span(), self.exprs.id(call));
+        let mut generics: &[Generic] = &[];
+        let signature = match self.resolution.res(ident) {
+            Some(Res::Def(id)) => match &self.resolution.def(id).item.definition {
+                Definition::Func(function) => {
+                    generics = &function.generics;
+                    let mut signature = Signature::of_function(self.resolution, function);
+                    signature.output = self.output(function, span);
+                    Some(signature)
+                }
+                _ => None,
+            },
+            Some(Res::Local(binding)) => self.local_method_signature(ident, binding, call),
+            Some(Res::ImplMethod(id)) => match &self.resolution.def(id).item.definition {
+                Definition::Impl(Impl::Trait(_, _, trait_ident, target, functions)) => {
+                    let name = ident.name();
+                    let function = functions.iter().find(|f| f.ident.name() == name);
+                    let signature = self.declared_signature(trait_ident, name, function);
+                    signature.map(|mut signature| {
+                        // Called by path, the receiver is passed like any other argument
+                        if signature.receiver {
+                            signature
+                                .parameters
+                                .insert(0, lower(self.resolution, target));
+                        }
+                        signature
+                    })
+                }
+                _ => None,
+            },
+            Some(Res::Param(_)) | None => None,
+        };
+        self.check_call(signature, generics, args, call, span)
+    }
+
+    /// Checks the arguments of the call `call` at `span` against the signature of the function it
+    /// calls, if known, and returns the type of the call
+    fn check_call(
+        &mut self,
+        signature: Option<Signature>,
+        generics: &[Generic],
+        args: &[Node<Expr>],
+        call: ExprId,
+        span: Span,
+    ) -> Ty {
+        let Some(signature) = signature else {
+            for arg in args {
+                self.check_expr(arg);
+            }
+            return Ty::Error;
+        };
+
+        if args.len() != signature.parameters.len() {
+            let kind = TypeErrorKind::ArgumentCount {
+                expected: signature.parameters.len(),
+                found: args.len(),
+            };
+            self.error(kind, span);
+        }
+        let mut inferred = HashMap::new();
+        for (index, arg) in args.iter().enumerate() {
+            match signature.parameters.get(index) {
+                Some(ty) if generics.is_empty() => self.expect(arg, ty),
+                Some(ty) => self.expect_generic(arg, ty, &mut inferred),
+                None => {
+                    self.check_expr(arg);
+                }
+            }
+        }
+        self.instantiate(generics, &mut inferred, call, span);
+        substitute(&self.resolved(&signature.output), &inferred)
+    }
+
+    /// Checks the type arguments a call of a generic function was inferred to have against the
+    /// bounds of its type parameters, and records them for instance resolution. Parameters that
+    /// could not be inferred become errors
+    fn instantiate(
+        &mut self,
+        generics: &[Generic],
+        inferred: &mut HashMap<Span, Ty>,
+        call: ExprId,
+        span: Span,
+    ) {
+        for generic in generics {
+            let ty = inferred
+                .entry(generic.ident.span())
+                .or_insert(Ty::Error)
+                .clone();
+            self.check_bounds(generic, &ty, span);
+            for bound in &generic.bounds {
+                if let Some(trait_id) = self.resolution.res_def(bound) {
+                    let bounds = self.bounds.entry(call).or_default();
+                    bounds.push((trait_id, ty.clone()));
+                }
+            }
+        }
+    }
+
+    /// Signature of a method call like `a.b.foo()`, where `binding` is the local `a` refers to
+    fn local_method_signature(
+        &mut self,
+        ident: &Ident,
+        binding: Span,
+        call: ExprId,
+    ) -> Option<Signature> {
+        let segments = ident.segments();
+        let (method, path) = segments.split_last()?;
+        let local = self.locals.get(&binding).cloned().unwrap_or(Ty::Error);
+        let receiver = self.check_fields(&local, &path[1..], ident.span());
+        self.method_signature(&receiver, method, ident.span(), call)
+    }
+
+    /// Signature of the method `method` called by `call` on a value of type `receiver`, reporting
+    /// errors at `span`. Methods of inherent impls are preferred over those of trait impls, and
+    /// impls not visible from the call are ignored
+    fn method_signature(
+        &mut self,
+        receiver: &Ty,
+        method: &str,
+        span: Span,
+        call: ExprId,
+    ) -> Option<Signature> {
+        let (id, arguments, bound) = match receiver {
+            Ty::Error => return None,
+            Ty::Infer(_) => {
+                self.error(TypeErrorKind::AnnotationsNeeded, span);
+                return None;
+            }
+            Ty::Struct(id, arguments, bound) => (Some(*id), arguments.clone(), bound.clone()),
+            Ty::Param(param) => {
+                let generic = self.resolution.generic(*param)?;
+                let bounds = generic.bounds.iter();
+                let traits: Vec<_> = bounds
+                    .filter_map(|bound| self.resolution.res_def(bound))
+                    .flat_map(|bound| implied_traits(self.resolution, bound))
+                    .collect();
+                return self.bounded_method(receiver, &traits, method, span);
+            }
+            Ty::Implementor(trait_id) => {
+                let mut traits = implied_traits(self.resolution, *trait_id);
+                let signature = self.bounded_method(receiver, &traits, method, span)?;
+                traits.retain(|&id| self.resolution.trait_method(id, method).is_some());
+                self.self_calls.insert(call, traits);
+                return Some(signature);
+            }
+            _ => (None, Vec::new(), Vec::new()),
+        };
+
+        let mut inherent = None;
+        let mut traits = Vec::new();
+        for impl_id in self.resolution.defs_of_kind(DefKind::Impl) {
+            let Definition::Impl(definition) = &self.resolution.def(impl_id).item.definition else {
+                continue;
+            };
+            if !self.resolution.is_visible(impl_id, self.module) {
+                continue;
+            }
+            let (trait_ident, struct_ident, functions) = match definition {
+                Impl::Trait(_, _, trait_ident, target, functions) => {
+                    (Some(trait_ident), target.ident(), functions)
+                }
+                Impl::Struct(struct_ident, functions) => (None, Some(struct_ident), functions),
+            };
+            if id.is_none() || struct_ident.and_then(|i| self.resolution.res_def(i)) != id {
+                continue;
+            }
+            let function = functions.iter().find(|f| f.ident.name() == method);
+            match trait_ident {
+                // An impl leaving out a default method uses the trait's body
+                Some(trait_ident)
+                    if function.is_some()
+                        || self
+                            .declared(trait_ident, method)
+                            .is_some_and(|m| m.body.is_some()) =>
+                {
+                    traits.push((trait_ident, function));
+                }
+                Some(_) => {}
+                None => inherent = inherent.or(function),
+            }
+        }
+
+        if let Some(function) = inherent {
+            let mut signature = Signature::of_function(self.resolution, function);
+            signature.output = self.output(function, span);
+            return self.takes_receiver(signature, method, span);
+        }
+        // Every impl of a trait shares the signature declared by the trait
+        if !traits.is_empty() {
+            let mut trait_ids: Vec<_> = traits
+                .iter()
+                .filter_map(|&(trait_ident, _)| self.resolution.res_def(trait_ident))
+                .collect();
+            trait_ids.sort();
+            trait_ids.dedup();
+            for &trait_id in &trait_ids {
+                self.check_stable(receiver, trait_id, span);
+            }
+            let signatures = traits
+                .iter()
+                .filter_map(|&(trait_ident, function)| {
+                    let trait_id = self.resolution.res_def(trait_ident)?;
+                    let signature = self.declared_signature(trait_ident, method, function)?;
+                    Some((trait_id, signature))
+                })
+                .collect();
+            let signature = self.common_signature(signatures, method, span)?;
+            let signature = self.takes_receiver(signature, method, span)?;
+            let call_info = MethodCall {
+                receiver: id.expect("trait impls are only found for structs"),
+                method: method.to_string(),
+                arguments,
+                bound,
+            };
+            self.method_calls.insert(call, call_info);
+            return Some(signature);
+        }
+
+        let kind = TypeErrorKind::NoMethod {
+            ty: to_type(self.resolution, receiver),
+            method: method.to_string(),
+        };
+        self.error(kind, span);
+        None
+    }
+
+    /// The signature of a method called on a value, which must take it as `self`
+    fn takes_receiver(
+        &mut self,
+        signature: Signature,
+        method: &str,
+        span: Span,
+    ) -> Option<Signature> {
+        if signature.receiver {
+            return Some(signature);
+        }
+        let kind = TypeErrorKind::NoReceiver {
+            method: method.to_string(),
+        };
+        self.error(kind, span);
+        None
+    }
+
+    /// Signature of a method called on a value of a type parameter, or of `self` in a default
+    /// method. The method must be declared by one of `traits`, the traits known to be implemented
+    /// by the type. Its impl is only known where the type is instantiated
+    fn bounded_method(
+        &mut self,
+        receiver: &Ty,
+        traits: &[DefId],
+        method: &str,
+        span: Span,
+    ) -> Option<Signature> {
+        let signatures: Vec<_> = traits
+            .iter()
+            .filter_map(|&trait_id| {
+                let m = self.resolution.trait_method(trait_id, method)?;
+                Some((trait_id, Signature::of_method(self.resolution, m)))
+            })
+            .collect();
+        if !signatures.is_empty() {
+            let signature = self.common_signature(signatures, method, span)?;
+            return self.takes_receiver(signature, method, span);
+        }
+        let kind = TypeErrorKind::NoMethod {
+            ty: to_type(self.resolution, receiver),
+            method: method.to_string(),
+        };
+        self.error(kind, span);
+        None
+    }
+
+    /// The signature shared by the declarations of `method` in `signatures`, each paired with its
+    /// trait. Reports the call as ambiguous if they differ
+    fn common_signature(
+        &mut self,
+        signatures: Vec<(DefId, Signature)>,
+        method: &str,
+        span: Span,
+    ) -> Option<Signature> {
+        let same = |a: &Signature, b: &Signature| {
+            a.receiver == b.receiver
+                && a.parameters.len() == b.parameters.len()
+                && a.parameters
+                    .iter()
+                    .zip(&b.parameters)
+                    .all(|(a, b)| compatible(a, b))
+                && compatible(&a.output, &b.output)
+        };
+        let (_, first) = signatures.first()?;
+        if signatures
+            .iter()
+            .all(|(_, signature)| same(first, signature))
+        {
+            return signatures
+                .into_iter()
+                .next()
+                .map(|(_, signature)| signature);
+        }
+        let mut traits: Vec<_> = signatures.iter().map(|&(trait_id, _)| trait_id).collect();
+        traits.sort();
+        traits.dedup();
+        let kind = TypeErrorKind::AmbiguousMethod {
+            method: method.to_string(),
+            traits: traits.iter().map(|&id| self.resolution.path(id)).collect(),
+        };
+        self.error(kind, span);
+        None
+    }
+
+    /// The method `name` declared by the trait of `trait_ident`
+    fn declared(&self, trait_ident: &Ident, name: &str) -> Option<&'a TraitMethod> {
+        let trait_id = self.resolution.res_def(trait_ident)?;
+        self.resolution.trait_method(trait_id, name)
+    }
+
+    /// Signature of the method `name` of an impl of the trait of `trait_ident`, implemented by
+    /// `function` unless the impl uses a default method. Every impl of a trait shares the
+    /// signature declared by the trait, the function's own is only used if the trait does not
+    /// declare it
+    fn declared_signature(
+        &self,
+        trait_ident: &Ident,
+        name: &str,
+        function: Option<&Function>,
+    ) -> Option<Signature> {
+        match (self.declared(trait_ident, name), function) {
+            (Some(m), _) => Some(Signature::of_method(self.resolution, m)),
+            (None, Some(function)) => Some(Signature::of_function(self.resolution, function)),
+            (None, None) => None,
+        }
+    }
+
+    /// Reports a `use` selecting an impl of `trait_id` other than the one `receiver` is bound to
+    fn check_stable(&mut self, receiver: &Ty, trait_id: DefId, span: Span) {
+        let Ty::Struct(struct_id, _, bound) = receiver else {
+            return;
+        };
+        let pair = Some((trait_id, *struct_id));
+        let Some(&bound) = bound
+            .iter()
+            .find(|&&id| self.resolution.impl_of(id) == pair)
+        else {
+            return;
+        };
+        let selected = self
+            .uses
+            .iter()
+            .rev()
+            .find(|&&id| self.resolution.impl_of(id) == pair);
+        if let Some(&selected) = selected.filter(|&&selected| selected != bound) {
+            let kind = TypeErrorKind::UnstableImpl {
+                ty: to_type(self.resolution, receiver),
+                bound: self.resolution.path(bound),
+                selected: self.resolution.path(selected),
+            };
+            self.error(kind, span);
+        }
+    }
+
+    fn check_struct_init(
+        &mut self,
+        ident: &Ident,
+        fields: &[(Ident, Node<Expr>)],
+        span: Span,
+    ) -> Ty {
+        let Some(id) = self.resolution.res_def(ident) else {
+            for (_, value) in fields {
+                self.check_expr(value);
+            }
+            return Ty::Error;
+        };
+
+        let declared = self.struct_fields(id);
+        let generics = self.struct_generics(id);
+        let mut inferred = HashMap::new();
+        let mut seen = HashSet::new();
+        for (field, value) in fields {
+            let name = field.name();
+            match declared.iter().find(|f| f.ident.name() == name) {
+                Some(_) if !seen.insert(name) => {
+                    self.check_expr(value);
+                    let kind = TypeErrorKind::DuplicateField {
+                        field: name.to_string(),
+                    };
+                    self.error(kind, field.span());
+                }
+                Some(declared) => {
+                    let ty = lower(self.resolution, &declared.field_type);
+                    if generics.is_empty() {
+                        self.expect(value, &ty);
+                    } else {
+                        self.expect_generic(value, &ty, &mut inferred);
+                    }
+                }
+                None => {
+                    self.check_expr(value);
+                    let kind = TypeErrorKind::NoField {
+                        ty: to_type(self.resolution, &Ty::Struct(id, Vec::new(), Vec::new())),
+                        field: name.to_string(),
+                    };
+                    self.error(kind, field.span());
+                }
+            }
+        }
+
+        let missing: Vec<_> = declared
+            .iter()
+            .map(|f| f.ident.name())
+            .filter(|name| !seen.contains(name))
+            .map(str::to_string)
+            .collect();
+        if !missing.is_empty() {
+            let kind = TypeErrorKind::MissingFields {
+                ty: to_type(self.resolution, &Ty::Struct(id, Vec::new(), Vec::new())),
+                fields: missing,
+            };
+            self.error(kind, span);
+        }
+
+        // Type arguments no field mentions stay unknown
+        let arguments = generics
+            .iter()
+            .map(|generic| {
+                let ty = inferred
+                    .get(&generic.ident.span())
+                    .cloned()
+                    .unwrap_or(Ty::Error);
+                self.check_bounds(generic, &ty, span);
+                ty
+            })
+            .collect();
+        Ty::Struct(id, arguments, Vec::new())
+    }
+
+    fn struct_fields(&self, id: DefId) -> &'a [StructField] {
+        let item: &'a Item = self.resolution.def(id).item;
+        match &item.definition {
+            Definition::Struct(_, _, fields) => fields,
+            _ => &[],
+        }
+    }
+
+    fn struct_generics(&self, id: DefId) -> &'a [Generic] {
+        let item: &'a Item = self.resolution.def(id).item;
+        match &item.definition {
+            Definition::Struct(_, generics, _) => generics,
+            _ => &[],
+        }
+    }
+
+    /// The type of a field of the struct `id` applied to `arguments`
+    fn field_type(&self, id: DefId, arguments: &[Ty], field: &StructField) -> Ty {
+        let arguments = self
+            .struct_generics(id)
+            .iter()
+            .map(|generic| generic.ident.span())
+            .zip(arguments.iter().cloned())
+            .collect();
+        substitute(&lower(self.resolution, &field.field_type), &arguments)
+    }
+
+    fn error(&mut self, kind: TypeErrorKind, span: Span) {
+        self.error_with_origin(kind, span, None);
+    }
+
+    fn error_with_origin(&mut self, kind: TypeErrorKind, span: Span, origin: Option<Label>) {
+        let error = TypeError { kind, span, origin };
+        let mut diagnostic = Diagnostic::error(error.to_string()).with_span(span);
+        if let Some(origin) = &error.origin {
+            diagnostic = diagnostic.with_label(origin.span, origin.message.clone());
+        }
+        self.session.emit(diagnostic);
+        self.errors.push(error);
+    }
+}
+
+/// Every function and impl method without a return type, see [`Checker::unchecked`]
+fn unchecked_functions<'a>(resolution: &Resolution<'a>) -> HashMap<Span, (DefId, &'a Function)> {
+    let mut unchecked = HashMap::new();
+    let defs = resolution.defs_of_kind(DefKind::Func);
+    for id in defs.chain(resolution.defs_of_kind(DefKind::Impl)) {
+        let functions = match &resolution.def(id).item.definition {
+            Definition::Func(function) => std::slice::from_ref(&**function),
+            Definition::Impl(Impl::Trait(.., functions) | Impl::Struct(_, functions)) => functions,
+            _ => continue,
+        };
+        for function in functions.iter().filter(|f| f.return_type.is_none()) {
+            unchecked.insert(function.ident.span(), (id, function));
+        }
+    }
+    unchecked
+}
+
+/// Converts a type written in the source. Unresolved struct names become [`Ty::Error`], as
+/// resolution already reported them. So are invalid impl bindings, which are left out, and missing
+/// type arguments
+pub(crate) fn lower(resolution: &Resolution, t: &Type) -> Ty {
+    let (ident, arguments, bindings) = match t {
+        Type::Unit => return Ty::Unit,
+        Type::I32 => return Ty::I32,
+        Type::F32 => return Ty::F32,
+        Type::Function(..) => return Ty::Error,
+        Type::Ident(ident) => (ident, &[][..], &[][..]),
+        Type::Indexed(ident, bindings) => (ident, &[][..], bindings.as_slice()),
+        Type::Applied(ident, arguments) => (ident, arguments.as_slice(), &[][..]),
+    };
+    if let Some(Res::Param(span)) = resolution.res(ident) {
+        return Ty::Param(span);
+    }
+    let id = match resolution.res_def(ident) {
+        Some(id) if resolution.def(id).kind == DefKind::Struct => id,
+        _ => return Ty::Error,
+    };
+    let Definition::Struct(_, generics, _) = &resolution.def(id).item.definition else {
+        return Ty::Error;
+    };
+    let arguments = (0..generics.len())
+        .map(|index| {
+            arguments
+                .get(index)
+                .map_or(Ty::Error, |t| lower(resolution, t))
+        })
+        .collect();
+
+    let mut bound: Vec<_> = bindings
+        .iter()
+        .filter_map(|binding| {
+            let trait_id = resolution.res_def(&binding.trait_ident)?;
+            let impl_id = resolution.res_def(&binding.impl_ident)?;
+            (resolution.impl_of(impl_id) == Some((trait_id, id))).then_some(impl_id)
+        })
+        .collect();
+    bound.sort();
+    bound.dedup();
+    Ty::Struct(id, arguments, bound)
+}
+
+/// Replaces the type parameters of `ty` that have a type in `arguments`
+pub(crate) fn substitute(ty: &Ty, arguments: &HashMap<Span, Ty>) -> Ty {
+    match ty {
+        Ty::Param(span) => arguments.get(span).cloned().unwrap_or(Ty::Param(*span)),
+        Ty::Struct(id, inner, bound) => Ty::Struct(
+            *id,
+            inner.iter().map(|ty| substitute(ty, arguments)).collect(),
+            bound.clone(),
+        ),
+        _ => ty.clone(),
+    }
+}
+
+/// Matches `actual` against `pattern`, giving each type parameter of `pattern` without a type in
+/// `inferred` the type it is matched with
+pub(crate) fn infer(pattern: &Ty, actual: &Ty, inferred: &mut HashMap<Span, Ty>) {
+    match (pattern, actual) {
+        (_, Ty::Error) => {}
+        (Ty::Param(span), _) => {
+            inferred.entry(*span).or_insert_with(|| actual.clone());
+        }
+        (Ty::Struct(id, arguments, _), Ty::Struct(actual_id, actual_arguments, _))
+            if id == actual_id =>
+        {
+            for (pattern, actual) in arguments.iter().zip(actual_arguments) {
+                infer(pattern, actual, inferred);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The types the type parameters of the generic impl `impl_id`, implementing a trait for
+/// `target`, have when the impl is used for its struct applied to `arguments`
+pub(crate) fn impl_arguments(
+    resolution: &Resolution,
+    impl_id: DefId,
+    target: &Type,
+    arguments: &[Ty],
+) -> HashMap<Span, Ty> {
+    let mut inferred = HashMap::new();
+    if let Some((_, struct_id)) = resolution.impl_of(impl_id) {
+        let actual = Ty::Struct(struct_id, arguments.to_vec(), Vec::new());
+        infer(&lower(resolution, target), &actual, &mut inferred);
+    }
+    inferred
+}
+
+/// The trait `id` and all of its supertraits, transitively
+pub(crate) fn implied_traits(resolution: &Resolution, id: DefId) -> Vec<DefId> {
+    let mut traits = vec![id];
+    let mut index = 0;
+    while let Some(&trait_id) = traits.get(index) {
+        for supertrait in resolution.supertraits(trait_id) {
+            if !traits.contains(&supertrait) {
+                traits.push(supertrait);
+            }
+        }
+        index += 1;
+    }
+    traits
+}
+
+/// Whether a value of type `found` can be used where `expected` is. Errors are compatible with
+/// everything, also as type arguments
+fn compatible(expected: &Ty, found: &Ty) -> bool {
+    match (expected, found) {
+        (Ty::Error, _) | (_, Ty::Error) => true,
+        (Ty::Struct(id, arguments, bound), Ty::Struct(found_id, found_arguments, found_bound)) => {
+            id == found_id
+                && bound == found_bound
+                && arguments
+                    .iter()
+                    .zip(found_arguments)
+                    .all(|(expected, found)| compatible(expected, found))
+        }
+        _ => expected == found,
+    }
+}
+
+/// Converts back to a source type. Structs, and the traits and impls they are bound to, are named
+/// by their fully qualified path
+pub(crate) fn to_type(resolution: &Resolution, ty: &Ty) -> Type {
+    match ty {
+        Ty::Unit | Ty::Error => Type::Unit,
+        Ty::Implementor(_) => Type::Ident(Ident::Simple("Self".into(), Span::default())),
+        Ty::Infer(_) => Type::Ident(Ident::Simple("_".into(), Span::default())),
+        Ty::I32 => Type::I32,
+        Ty::F32 => Type::F32,
+        Ty::Param(span) => {
+            let name = resolution
+                .generic(*span)
+                .map_or_else(String::new, |generic| generic.ident.name().to_string());
+            Type::Ident(Ident::Simple(name, Span::default()))
+        }
+        Ty::Struct(id, arguments, bound) if bound.is_empty() && arguments.is_empty() => {
+            Type::Ident(path_ident(resolution, *id))
+        }
+        Ty::Struct(id, arguments, bound) if bound.is_empty() => Type::Applied(
+            path_ident(resolution, *id),
+            arguments.iter().map(|ty| to_type(resolution, ty)).collect(),
+        ),
+        Ty::Struct(id, _, bound) => {
+            let bindings = bound
+                .iter()
+                .filter_map(|&impl_id| {
+                    let (trait_id, _) = resolution.impl_of(impl_id)?;
+                    Some(ImplBinding {
+                        trait_ident: path_ident(resolution, trait_id),
+                        impl_ident: path_ident(resolution, impl_id),
+                    })
+                })
+                .collect();
+            Type::Indexed(path_ident(resolution, *id), bindings)
+        }
+    }
+}
+
+fn path_ident(resolution: &Resolution, id: DefId) -> Ident {
+    let mut path: Vec<String> = resolution
+        .path(id)
+        .split("::")
+        .map(str::to_string)
+        .collect();
+    let name = path.pop().unwrap_or_default();
+    if path.is_empty() {
+        Ident::Simple(name, Span::default())
+    } else {
+        Ident::Qualified(path, name, Span::default())
+    }
+}
+
+/// The span of the expression producing the value of `expr`, which is the tail of a block
+fn value_span(expr: &Node<Expr>) -> Span {
+    match &expr.node {
+        Expr::Block(_, Some(tail)) => value_span(tail),
+        _ => expr.span(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+    use crate::resolve::resolve;
+
+    fn errors(input: &str) -> Vec<String> {
+        let program = parse(input).unwrap();
+        let mut diagnostics = Vec::new();
+        let mut session = Session::new(&mut diagnostics);
+        let resolution = resolve(&mut session, &program);
+        check(&mut session, &resolution, &program);
+        diagnostics.into_iter().map(|d| d.message).collect()
+    }
+
+    #[test]
+    fn test_check_well_typed() {
+        let input = r"
+struct S { a: i32, b: f32 }
+impl S {
+    fn scaled(self, x: f32) -> f32 { x * 2.0 }
+}
+fn f(s: S, x: i32) -> i32 {
+    let t: S = S { b: s.b, a: x };
+    let y = if t.a > 0 && !(x == 1) then { t.a } else { -x };
+    if y < 3 then { f(t, y); };
+    y % 2
+}
+fn g(s: S) -> f32 { s.scaled(s.b) }
+fn h() {}
+";
+        assert_eq!(errors(input), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_check_expressions() {
+        let input = r"
+fn f(x: i32, y: f32) -> i32 {
+    let a = x + y;
+    let b: f32 = x;
+    let c = -(x < 2);
+    let d = !y;
+    let e = if y then { 1 } else { 2.0 };
+    if x then { 1 };
+    y
+}
+";
+        assert_eq!(
+            errors(input),
+            [
+                "cannot apply `+` to `i32` and `f32`",
+                "mismatched types, expected `f32`, found `i32`",
+                "cannot apply `!` to `f32`",
+                "mismatched types, expected `i32`, found `f32`",
+                "mismatched types, expected `i32`, found `f32`",
+                "mismatched types, expected `()`, found `i32`",
+                "mismatched types, expected `i32`, found `f32`",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_invocations() {
+        let input = r"
+mod M {
+    pub struct S { a: i32 }
+}
+fn f(a: i32, s: M::S) -> M::S { s }
+fn g(s: M::S) {
+    f(1);
+    f(1.0, s);
+    f(1, 2);
+    s.missing();
+    f
+}
+";
+        assert_eq!(
+            errors(input),
+            [
+                "expected 2 arguments, found 1",
+                "mismatched types, expected `i32`, found `f32`",
+                "mismatched types, expected `M::S`, found `i32`",
+                "no method named `missing` found for `M::S`",
+                "expected value, found function `f`",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_impl_method_calls() {
+        let input = r"
+struct S {}
+trait T { fn foo(a: i32) -> i32 }
+impl C of T for S { fn foo(a: i32) -> i32 { a } }
+fn f() -> i32 {
+    C::foo(1.0);
+    C::foo()
+}
+fn g() -> f32 { C::foo(1) }
+";
+        assert_eq!(
+            errors(input),
+            [
+                "mismatched types, expected `i32`, found `f32`",
+                "expected 1 argument, found 0",
+                "mismatched types, expected `f32`, found `i32`",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_default_methods() {
+        let input = r"
+struct S {}
+trait T {
+    fn foo(self, a: i32) -> i32 { a + 1 }
+    fn bar(self, a: i32) -> f32 { a }
+}
+impl C of T for S { fn bar(self, a: i32) -> f32 { 1.0 } }
+fn f(s: S) -> i32 { s.foo(1) + C::foo(s, 2) }
+fn g(s: S) -> f32 { s.foo(1) }
+";
+        assert_eq!(
+            errors(input),
+            [
+                "mismatched types, expected `f32`, found `i32`",
+                "mismatched types, expected `f32`, found `i32`",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_receivers() {
+        let input = r"
+struct S { a: i32 }
+trait T {
+    fn get(self) -> i32
+    fn add(self, b: i32) -> i32 { self.get() + b }
+    fn make() -> i32
+}
+impl S { fn get(self) -> f32 { 1.0 } }
+impl C of T for S {
+    fn get(self) -> i32 { self.a }
+    fn make() -> i32 { 0 }
+}
+fn f(s: S) -> i32 { s.add(1) + C::get(s) + C::add(s, 2) + s.make() }
+fn inherent(s: S) -> f32 { s.get() }
+struct W { s: S }
+fn id(s: S) -> S { s }
+fn atoms(w: W) -> i32 {
+    (w.s).add(1) + w.s.add(2) + id(w.s).add(3) + S { a: 1 }.add(4) + { w.s }.add(5) + (w.s).make()
+}
+fn g(s: S) {
+    s.add();
+    C::get();
+    C::get(1);
+}
+trait U { fn bad(self) -> i32 { self.a + self.missing() } }
+";
+        assert_eq!(
+            errors(input),
+            [
+                "method `make` has no `self` parameter",
+                "method `make` has no `self` parameter",
+                "expected 1 argument, found 0",
+                "expected 1 argument, found 0",
+                "mismatched types, expected `S`, found `i32`",
+                "no field `a` on `Self`",
+                "no method named `missing` found for `Self`",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_ambiguous_methods() {
+        let input = r"
+struct S {}
+trait T { fn same(self) -> i32 fn differ(self) -> i32 }
+trait U { fn same(self) -> i32 fn differ(self, a: i32) -> f32 }
+impl A of T for S { fn same(self) -> i32 { 1 } fn differ(self) -> i32 { 2 } }
+impl B of U for S { fn same(self) -> i32 { 3 } fn differ(self, a: i32) -> f32 { 4.0 } }
+fn f(s: S) -> i32 { s.same() + s.differ() }
+trait V: T + U { fn g(self) -> i32 { self.same() + self.differ(1) } }
+";
+        assert_eq!(
+            errors(input),
+            [
+                "method `differ` is declared with different signatures by traits `T`, `U`",
+                "method `differ` is declared with different signatures by traits `T`, `U`",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_infer_types() {
+        let input = r"
+struct S { a: i32 }
+trait T { fn get(self) -> i32 }
+impl T for S { fn get(self) { self.a } }
+fn twice(x: i32) { x * 2 }
+fn earlier(x: i32) { x }
+fn later() { earlier(1) + 1 }
+fn f(s: S) -> i32 { let a = twice(s.get()); let b = a; b + later() + before(s) }
+fn before(s: S) { after() + s.half() }
+fn after() { 1 }
+impl S { fn half(self) { self.a / 2 } }
+fn unit() { }
+fn use_unit() -> () { unit() }
+";
+        assert_eq!(errors(input), Vec::<String>::new());
+
+        let input = r"
+trait T { fn get() -> i32 }
+struct S {}
+impl T for S { fn get() { 1.0 } }
+fn half() { 0.5 }
+fn f() -> i32 { let a = half(); a }
+fn loops() { loops() }
+fn g() -> i32 { let b = loops(); b.a }
+fn first() -> i32 { second() }
+fn second() { 1 }
+fn ping(n: i32) { pong(n) }
+fn pong(n: i32) { ping(n) }
+";
+        let program = parse(input).unwrap();
+        let mut diagnostics = Vec::new();
+        let mut session = Session::new(&mut diagnostics);
+        let resolution = resolve(&mut session, &program);
+        check(&mut session, &resolution, &program);
+        let errors: Vec<_> = diagnostics
+            .iter()
+            .map(|d| {
+                (
+                    d.message.as_str(),
+                    d.labels.first().map(|l| l.message.as_str()),
+                )
+            })
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                (
+                    "mismatched types, expected `i32`, found `f32`",
+                    Some("`i32` inferred here")
+                ),
+                (
+                    "mismatched types, expected `i32`, found `f32`",
+                    Some("`f32` inferred here")
+                ),
+                (
+                    "return type of `loops` must be written out to call it recursively",
+                    None
+                ),
+                (
+                    "return type of `ping` must be written out to call it recursively",
+                    None
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_generics() {
+        let input = r"
+struct A { x: i32 }
+struct B {}
+trait Ord { fn cmp(self, a: i32) -> i32 }
+trait Show { fn show(self) -> i32 }
+impl Ord for A { fn cmp(self, a: i32) -> i32 { a } }
+impl Show for A { fn show(self) -> i32 { 1 } }
+struct List<T> { head: T }
+impl<T: Show> Show for List<T> { fn show(self) -> i32 { 2 } }
+fn max<T: Ord>(a: T, b: T) -> T { if a.cmp(1) > 0 then { a } else { b } }
+fn shown<T: Show>(t: T) -> i32 { t.show() }
+fn f(a: A) -> i32 {
+    let l = List { head: a };
+    let x: A = max(a, a);
+    let y: i32 = l.head.x;
+    shown(l) + shown(List { head: List { head: a } })
+}
+fn g(a: A, b: B) {
+    max(b, b);
+    max(a, 1);
+    shown(List { head: b });
+    let l: List = List { head: a };
+    let m: List<A, B> = List { head: a };
+    let n: List<B> = List { head: a };
+}
+fn h<T>(t: T) -> i32 { t.show() }
+";
+        assert_eq!(
+            errors(input),
+            [
+                "the trait bound `B: Ord` is not satisfied",
+                "mismatched types, expected `A`, found `i32`",
+                "the trait bound `List<B>: Show` is not satisfied",
+                "struct `List` takes 1 type argument, found 0",
+                "struct `List` takes 1 type argument, found 2",
+                "mismatched types, expected `List<B>`, found `List<A>`",
+                "no method named `show` found for `T`",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_struct_init() {
+        let input = r"
+struct Foo { a: i32, b: f32 }
+fn f(foo: Foo) {
+    Foo { a: 1, b: 2 };
+    Foo { a: 1 };
+    Foo { a: 1, a: 2, b: 0.5, c: 3 };
+    foo.c;
+}
+";
+        assert_eq!(
+            errors(input),
+            [
+                "mismatched types, expected `f32`, found `i32`",
+                "missing fields `b` in initializer of `Foo`",
+                "field `a` specified more than once",
+                "no field `c` on `Foo`",
+                "no field `c` on `Foo`",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_impl_indexed_types() {
+        let input = r"
+struct S {}
+trait T { fn foo(self) -> i32 }
+trait U { fn bar(self) -> i32 }
+impl B of T for S { fn foo(self) -> i32 { 1 } }
+impl C of T for S { fn foo(self) -> i32 { 2 } }
+impl D of U for S { fn bar(self) -> i32 { 3 } }
+fn built() -> S[T = C] { let s: S[U = D, T = C] = S {}; { S {} } }
+fn stable(s: S[T = C]) -> i32 { use C in s.foo() + s.foo() }
+fn unstable(s: S[T = C]) -> i32 { use B in s.foo() }
+fn rebound(s: S[T = B]) -> S[T = C] { s }
+fn unbound(s: S) -> S[T = C] { s }
+fn invalid(s: S[T = D, U = D, T = C, T = B]) {}
+";
+        assert_eq!(
+            errors(input),
+            [
+                "value of type `S[T = C]` is bound to impl `C`, but `B` is selected",
+                "mismatched types, expected `S[T = C]`, found `S[T = B]`",
+                "mismatched types, expected `S[T = C]`, found `S`",
+                "`D` is not an impl of `T` for `S`",
+                "trait `T` is bound more than once",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_annotate() {
+        let input = "struct S { a: i32 }\nfn f(s: S) -> i32 { let b = s.a + 1; b }";
+        let program = parse(input).unwrap();
+        let mut diagnostics = Vec::new();
+        let mut session = Session::new(&mut diagnostics);
+        let results = {
+            let resolution = resolve(&mut session, &program);
+            check(&mut session, &resolution, &program)
+        };
+        let program = annotate(program, results).unwrap();
+
+        let Definition::Func(function) = &program.nodes[1].definition else {
+            panic!("Expected function");
+        };
+        let Expr::Block(statements, Some(tail)) = &function.body.node else {
+            panic!("Expected block with tail");
+        };
+        let Expr::Let(_, _, value) = &statements[0].node else {
+            panic!("Expected let");
+        };
+        assert!(matches!(value.get_annotation(), Type::I32));
+        assert!(matches!(statements[0].get_annotation(), Type::Unit));
+        assert!(matches!(tail.get_annotation(), Type::I32));
+    }
+
+    #[test]
+    fn test_annotate_unspanned_nodes() {
+        let mut program = parse("fn f() -> i32 { 0 }").unwrap();
+        let Definition::Func(function) = &mut program.nodes[0].definition else {
+            panic!("Expected function");
+        };
+        // `{ 1.5; 2 }`, built without the spans telling the nodes apart
+        let literal = |literal| Box::new(Node::from(Expr::Literal(literal)));
+        function.body = Node::from(Expr::Block(
+            vec![*literal(Literal::F32(1.5))],
+            Some(literal(Literal::I32(2))),
+        ));
+
+        let mut diagnostics = Vec::new();
+        let mut session = Session::new(&mut diagnostics);
+        let results = {
+            let resolution = resolve(&mut session, &program);
+            check(&mut session, &resolution, &program)
+        };
+        let program = annotate(program, results).unwrap();
+        assert!(diagnostics.is_empty());
+
+        let Definition::Func(function) = &program.nodes[0].definition else {
+            panic!("Expected function");
+        };
+        let Expr::Block(statements, Some(tail)) = &function.body.node else {
+            panic!("Expected block with tail");
+        };
+        assert_eq!(function.body.get_annotation::<Type>(), &Type::I32);
+        assert_eq!(statements[0].get_annotation::<Type>(), &Type::F32);
+        assert_eq!(tail.get_annotation::<Type>(), &Type::I32);
+    }
+}
@@ -0,0 +1,26 @@
+use orphan_instance_lang::parser::parse;
+use orphan_instance_lang::resolve::resolve;
+use orphan_instance_lang::session::Session;
+use test_each_file::test_each_file;
+
+test_each_file! { in "./orphan-instance-lang/tests/name-error" => name_error }
+fn name_error(content: &str) {
+    let program = parse(content).expect("name error tests must parse");
+
+    let mut diagnostics = Vec::new();
+    let mut session = Session::new(&mut diagnostics);
+    resolve(&mut session, &program);
+    assert!(session.has_errors());
+
+    // Every error, in the order reported, is written in the fixture as a `// ERROR: ` line
+    let errors: Vec<_> = diagnostics
+        .iter()
+        .filter(|d| d.is_error())
+        .map(|d| d.message.as_str())
+        .collect();
+    let expected: Vec<_> = content
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("// ERROR: "))
+        .collect();
+    assert_eq!(errors, expected);
+}
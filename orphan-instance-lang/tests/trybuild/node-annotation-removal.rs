@@ -0,0 +1,21 @@
+extern crate orphan_instance_lang;
+use orphan_instance_lang::ast::Type;
+use orphan_instance_lang::instance::ResolvedImpl;
+use orphan_instance_lang::node::Node;
+use orphan_instance_lang::resolve::DefId;
+
+#[rustfmt::skip]
+fn doesnt_compile() {
+    let node: Node<i32> = 0.into();
+    let _ = node.remove_annotation::<Type, _>(); // Shouldn't be able to remove annotation not added
+
+    let node: Node<i32> = 0.into();
+    let node = node.add_annotation(Type::I32).add_annotation(ResolvedImpl(Some(DefId(0))));
+    let (mut node, _) = node.remove_annotation::<Type, _>();
+    let _: &ResolvedImpl = node.get_annotation::<ResolvedImpl>();
+    let _: &Type = node.get_annotation::<Type>(); // Shouldn't be able to get annotation after it is removed
+    let _: &mut Type = node.get_annotation_mut::<Type>(); // Shouldn't be able to change annotation after it is removed
+    let _ = node.remove_annotation::<Type, _>(); // Shouldn't be able to remove annotation twice
+}
+
+fn main() {}
@@ -0,0 +1,43 @@
+extern crate orphan_instance_lang;
+use orphan_instance_lang::ast::{Span, Type};
+use orphan_instance_lang::instance::ResolvedImpl;
+use orphan_instance_lang::node::Node;
+use orphan_instance_lang::resolve::{DefId, ResolvedName, Scope};
+
+#[rustfmt::skip]
+fn doesnt_compile() {
+    let node: Node<i32> = 0.into();
+    let _: &Span = node.get_annotation::<Span>(); // Shouldn't be able to get annotation not added
+    let _: &ResolvedName = node.get_annotation::<ResolvedName>(); // Shouldn't be able to get annotation not added
+    let _: &ResolvedImpl = node.get_annotation::<ResolvedImpl>(); // Shouldn't be able to get annotation not added
+    let _: &Scope = node.get_annotation::<Scope>(); // Shouldn't be able to get annotation not added
+
+    let node = node.add_annotation(ResolvedName(Some(DefId(0))));
+    let _: &ResolvedName = node.get_annotation::<ResolvedName>();
+    let _: &Scope = node.get_annotation::<Scope>(); // Shouldn't be able to get annotation before it is added
+    let node = node.add_annotation(Scope(None));
+    let node = node.add_annotation(ResolvedImpl(Some(DefId(1))));
+    let node = node.add_annotation(Type::I32);
+    let _: &ResolvedName = node.get_annotation::<ResolvedName>();
+    let _: &ResolvedImpl = node.get_annotation::<ResolvedImpl>();
+    let _: &Scope = node.get_annotation::<Scope>();
+    let _: &Type = node.get_annotation::<Type>();
+
+    let full = || {
+        Node::from(0)
+            .add_annotation(Type::I32)
+            .add_annotation(ResolvedImpl(Some(DefId(1))))
+            .add_annotation(Scope(None))
+            .add_annotation(ResolvedName(Some(DefId(0))))
+    };
+    let _ = full().add_annotation::<Type>(Type::I32); // Shouldn't be able to add annotation twice
+    let _ = full().add_annotation::<ResolvedName>(ResolvedName(Some(DefId(0)))); // Shouldn't be able to add annotation twice
+    let _ = full().add_annotation::<ResolvedImpl>(ResolvedImpl(Some(DefId(1)))); // Shouldn't be able to add annotation twice
+    let _ = full().add_annotation::<Scope>(Scope(None)); // Shouldn't be able to add annotation twice
+
+    let node = Node::from(0).add_annotation(Span::new(0, 1));
+    let _: &Span = node.get_annotation::<Span>();
+    let _ = node.add_annotation::<Span>(Span::new(0, 1)); // Shouldn't be able to add annotation twice
+}
+
+fn main() {}
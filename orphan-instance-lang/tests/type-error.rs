@@ -0,0 +1,26 @@
+use orphan_instance_lang::driver::check;
+use orphan_instance_lang::parser::parse;
+use orphan_instance_lang::session::Session;
+use test_each_file::test_each_file;
+
+test_each_file! { in "./orphan-instance-lang/tests/type-error" => type_error }
+fn type_error(content: &str) {
+    let program = parse(content).expect("type error tests must parse");
+
+    let mut diagnostics = Vec::new();
+    let mut session = Session::new(&mut diagnostics);
+    let checked = check(&mut session, program);
+    assert!(checked.is_none());
+
+    // Every error, in the order reported, is written in the fixture as a `// ERROR: ` line
+    let errors: Vec<_> = diagnostics
+        .iter()
+        .filter(|d| d.is_error())
+        .map(|d| d.message.as_str())
+        .collect();
+    let expected: Vec<_> = content
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("// ERROR: "))
+        .collect();
+    assert_eq!(errors, expected);
+}